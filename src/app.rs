@@ -1,44 +1,131 @@
 use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
+use crate::block_template::{self, BlockTemplate};
+use crate::bribe::BribeConfig;
 use crate::cli::Config;
+use crate::coin_selection::{self, Selection, Strategy};
+use crate::coinbase::CoinbaseConfig;
+use crate::fee_estimator::{self, BlockFees, FeeEstimate};
+use crate::index::{self, Index, TransactionLocation};
+use crate::labels::Labels;
+use crate::main_proxy;
+use crate::policy::{self, Policy};
 use crate::thunder;
 use ddk::bitcoin;
 use ddk::drivechain::MainClient;
 use ddk::jsonrpsee;
 use ddk::node::State as _;
-use ddk::types::{OutPoint, Output, Transaction};
+use ddk::types::{
+    Address, BlockHash, Content, GetValue, MerkleRoot, OutPoint, Output, Transaction, Txid,
+};
+use serde::{Deserialize, Serialize};
 use thunder::{Miner, Node, Thunder, ThunderState, Wallet};
 
+/// How often the index checks whether the node connected or disconnected blocks.
+const INDEX_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+/// Database that ddk's node keeps in the datadir.
+const NODE_DB: &str = "data.mdb";
+const INDEX_DB: &str = "index.mdb";
+
+/// Counters for the blind merged mining attempts made by this node, manual and automatic.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MiningStats {
+    pub attempts: u64,
+    /// Attempts that got BMM confirmed and connected a block.
+    pub successes: u64,
+    pub failures: u64,
+    /// Failed attempts since the last success, used to escalate the bribe.
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+}
+
+/// Handle to the node, wallet and miner. It is cheap to clone, so that the GUI and the RPC server
+/// can share it.
+#[derive(Clone)]
 pub struct App {
-    pub node: Node,
-    pub wallet: Wallet,
-    pub miner: Miner,
-    pub utxos: HashMap<OutPoint, Output<Thunder>>,
-    pub transaction: Transaction<Thunder>,
-    runtime: tokio::runtime::Runtime,
+    pub node: Arc<Node>,
+    pub wallet: Arc<Wallet>,
+    pub miner: Arc<tokio::sync::Mutex<Miner>>,
+    pub utxos: Arc<RwLock<HashMap<OutPoint, Output<Thunder>>>>,
+    pub mining_stats: Arc<Mutex<MiningStats>>,
+    pub bribe: Arc<Mutex<BribeConfig>>,
+    pub coinbase: Arc<CoinbaseConfig>,
+    pub policy: Arc<Policy>,
+    pub labels: Arc<Mutex<Labels>>,
+    /// Highest fee in sats that `sign_and_send` pays.
+    pub max_fee: u64,
+    pub network: bitcoin::Network,
+    index: Index,
+    /// Why the index couldn't catch up with the node the last time it tried, if it couldn't.
+    index_error: Arc<Mutex<Option<String>>>,
+    /// Height that `blocks_until_spendable` was last asked about, and the first height that can
+    /// spend every output it looked up for it, so that the GUI doesn't read the index for every
+    /// UTXO in every frame. Cleared when the height changes.
+    spendable_at: Arc<Mutex<(u32, HashMap<OutPoint, Option<u32>>)>>,
+    /// Fees of the recent blocks that `estimate_fees` looked at, by block hash.
+    block_fees: Arc<Mutex<HashMap<BlockHash, BlockFees>>>,
+    /// Change address of the transactions that are built until one that pays to it is sent, so
+    /// that building transactions that are never sent doesn't use up wallet addresses.
+    change_address: Arc<Mutex<Option<Address>>>,
+    runtime: tokio::runtime::Handle,
+}
+
+/// Delete the index, it is built again from the node's chain when the app starts.
+pub fn remove_index(datadir: &Path) -> Result<(), Error> {
+    match std::fs::remove_dir_all(datadir.join(INDEX_DB)) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Format an error together with all of its sources, the same way everywhere it is shown.
+pub fn format_error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(&format!(": {err}"));
+        source = err.source();
+    }
+    message
 }
 
 impl App {
-    pub fn new(config: &Config) -> Result<Self, Error> {
-        // Node launches some tokio tasks for p2p networking, that is why we need a tokio runtime
-        // here.
-        let runtime = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()?;
+    pub fn new(config: &Config, runtime: tokio::runtime::Handle) -> Result<Self, Error> {
         let wallet = Wallet::new(&config.datadir.join("wallet.mdb"))?;
+        let labels = Labels::load(&config.datadir.join("labels.json"))?;
+        // With a cookie file the node and the miner go through a proxy that adds the credentials,
+        // so that they keep working when the mainchain node is restarted with a new cookie.
+        let (main_addr, main_user, main_password) = match &config.main_cookie {
+            Some(main_cookie) => {
+                let proxy = runtime
+                    .block_on(async { main_proxy::spawn(config.main_addr, main_cookie.clone()) })?;
+                (proxy.addr, proxy.user, proxy.password)
+            }
+            None => (
+                config.main_addr,
+                config.main_user.clone(),
+                config.main_password.clone(),
+            ),
+        };
         let miner = Miner::new(
             ThunderState::THIS_SIDECHAIN,
-            config.main_addr,
-            &config.main_user,
-            &config.main_password,
+            main_addr,
+            &main_user,
+            &main_password,
         )?;
+        // Node launches some tokio tasks for p2p networking, that is why we need to be inside of
+        // the tokio runtime here.
         let node = runtime.block_on(async {
             let node = match Node::new(
                 &config.datadir,
                 config.net_addr,
-                config.main_addr,
-                &config.main_user,
-                &config.main_password,
+                main_addr,
+                &main_user,
+                &main_password,
             ) {
                 Ok(node) => node,
                 Err(err) => return Err(err),
@@ -55,87 +142,553 @@ impl App {
             }
             utxos
         };
-        Ok(Self {
-            node,
-            wallet,
-            miner,
-            utxos,
-            transaction: Transaction {
-                inputs: vec![],
-                outputs: vec![],
-            },
+        let index = Index::open(&config.datadir.join(INDEX_DB))?;
+        let app = Self {
+            node: Arc::new(node),
+            wallet: Arc::new(wallet),
+            miner: Arc::new(tokio::sync::Mutex::new(miner)),
+            utxos: Arc::new(RwLock::new(utxos)),
+            mining_stats: Arc::new(Mutex::new(MiningStats::default())),
+            bribe: Arc::new(Mutex::new(config.bribe.clone())),
+            coinbase: Arc::new(config.coinbase.clone()),
+            policy: Arc::new(config.policy.clone()),
+            labels: Arc::new(Mutex::new(labels)),
+            max_fee: config.max_fee,
+            network: config.network,
+            index,
+            index_error: Arc::new(Mutex::new(None)),
+            spendable_at: Arc::new(Mutex::new((0, HashMap::new()))),
+            block_fees: Arc::new(Mutex::new(HashMap::new())),
+            change_address: Arc::new(Mutex::new(None)),
             runtime,
-        })
+        };
+        app.spawn(app.clone().sync_index());
+        Ok(app)
+    }
+
+    /// Keep the index in sync with the node in the background, indexing a long chain for the
+    /// first time takes a while.
+    async fn sync_index(self) {
+        let mut interval = tokio::time::interval(INDEX_SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            let index = self.index.clone();
+            let node = self.node.clone();
+            let index_error = match tokio::task::spawn_blocking(move || index.sync(&node)).await {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => Some(format_error_chain(&err)),
+                Err(err) => Some(format_error_chain(&err)),
+            };
+            *self.index_error.lock().unwrap() = index_error;
+        }
+    }
+
+    /// The index, unless the last attempt to catch up with the node failed.
+    fn index(&self) -> Result<&Index, Error> {
+        match &*self.index_error.lock().unwrap() {
+            Some(index_error) => Err(Error::IndexSync(index_error.clone())),
+            None => Ok(&self.index),
+        }
+    }
+
+    /// Run a future to completion on the app's tokio runtime, while node tasks keep running in the
+    /// background.
+    pub fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
     }
 
-    pub fn sign_and_send(&mut self) -> Result<(), Error> {
-        let authorized_transaction = self.wallet.authorize(self.transaction.clone())?;
+    /// Run `f` on the runtime's blocking threads, for work like reading many blocks that would
+    /// hold up other tasks.
+    pub async fn run_blocking<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(App) -> Result<T, Error> + Send + 'static,
+    {
+        let app = self.clone();
         self.runtime
-            .block_on(self.node.submit_transaction(&authorized_transaction))?;
-        self.transaction = Transaction {
-            inputs: vec![],
-            outputs: vec![],
+            .spawn_blocking(move || f(app))
+            .await
+            .map_err(|_| Error::TaskCancelled)?
+    }
+
+    /// Run a future in the background on the app's tokio runtime.
+    pub fn spawn<F>(&self, future: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.runtime.spawn(future)
+    }
+
+    /// Pick wallet UTXOs that can be spent in the next block and are worth at least `target` sats.
+    pub fn select_inputs(&self, strategy: Strategy, target: u64) -> Result<Selection, Error> {
+        let height = self.node.get_height()? + 1;
+        let mut utxos = vec![];
+        for (outpoint, output) in self.utxos.read().unwrap().iter() {
+            // Coinbases that aren't indexed yet can't be checked for maturity, they are left out.
+            match self.blocks_until_spendable(outpoint, output, height) {
+                Ok(None) => utxos.push((*outpoint, output.clone())),
+                Ok(Some(_)) | Err(Error::UnknownCoinbase(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        // Change worth less than the dust threshold could not be sent anyway.
+        let change_tolerance = self.policy.dust_threshold;
+        coin_selection::select(strategy, &utxos, target, change_tolerance).ok_or_else(|| {
+            Error::NotEnoughFunds {
+                available: utxos.iter().map(|(_, output)| output.get_value()).sum(),
+                required: target,
+            }
+        })
+    }
+
+    /// Wallet address for change, the same one until `sign_and_send` sends a transaction that
+    /// pays to it.
+    pub fn get_change_address(&self) -> Result<Address, Error> {
+        let mut change_address = self.change_address.lock().unwrap();
+        match *change_address {
+            Some(address) => Ok(address),
+            None => {
+                let address = self.wallet.get_new_address()?;
+                *change_address = Some(address);
+                Ok(address)
+            }
+        }
+    }
+
+    /// Build a transaction that spends the wallet UTXOs `inputs` on `outputs` and `fee`, or UTXOs
+    /// picked with the default coin selection strategy if `inputs` is empty. Whatever is left goes
+    /// back to the change address, change below the dust threshold goes to the fee.
+    pub fn create_transaction(
+        &self,
+        inputs: Vec<OutPoint>,
+        outputs: Vec<Output<Thunder>>,
+        fee: u64,
+    ) -> Result<Transaction<Thunder>, Error> {
+        let change_address = self.get_change_address()?;
+        let (transaction, _) = self.build_transaction(inputs, outputs, fee, change_address)?;
+        Ok(transaction)
+    }
+
+    /// Like `create_transaction` with picked inputs, but with a fee rate in sats per byte. Returns
+    /// the transaction and the fee it pays.
+    pub fn create_transaction_with_fee_rate(
+        &self,
+        outputs: Vec<Output<Thunder>>,
+        fee_rate: u64,
+    ) -> Result<(Transaction<Thunder>, u64), Error> {
+        let change_address = self.get_change_address()?;
+        // The size depends on the inputs and the change, which depend on the fee, so raise the fee
+        // until it covers the size. It only goes up, so this ends at the latest when the wallet
+        // runs out of funds.
+        let mut fee = 0;
+        loop {
+            let (transaction, paid) =
+                self.build_transaction(vec![], outputs.clone(), fee, change_address)?;
+            let required = fee_rate
+                .checked_mul(policy::estimate_size(&transaction)?)
+                .ok_or(Error::ValueOverflow)?;
+            if paid >= required {
+                return Ok((transaction, paid));
+            }
+            fee = required;
+        }
+    }
+
+    /// Returns the transaction and the fee it pays, which is more than `fee` if the change was
+    /// too small for an output.
+    fn build_transaction(
+        &self,
+        inputs: Vec<OutPoint>,
+        mut outputs: Vec<Output<Thunder>>,
+        fee: u64,
+        change_address: Address,
+    ) -> Result<(Transaction<Thunder>, u64), Error> {
+        let required = Self::required_value(&outputs, fee)?;
+        let (inputs, value_in) = self.get_or_select_inputs(inputs, required)?;
+        if value_in < required {
+            return Err(Error::NotEnoughFunds {
+                available: value_in,
+                required,
+            });
+        }
+        let change = value_in - required;
+        if change > 0 && change >= self.policy.dust_threshold {
+            outputs.push(Output {
+                address: change_address,
+                content: Content::Value(change),
+            });
+            Ok((Transaction { inputs, outputs }, fee))
+        } else {
+            let fee = fee.checked_add(change).ok_or(Error::ValueOverflow)?;
+            Ok((Transaction { inputs, outputs }, fee))
+        }
+    }
+
+    /// Value of `outputs` plus `fee`.
+    fn required_value(outputs: &[Output<Thunder>], fee: u64) -> Result<u64, Error> {
+        outputs
+            .iter()
+            .map(GetValue::get_value)
+            .try_fold(fee, u64::checked_add)
+            .ok_or(Error::ValueOverflow)
+    }
+
+    /// Value of wallet UTXOs that no mempool transaction spends yet.
+    fn get_inputs_value(&self, inputs: &[OutPoint]) -> Result<u64, Error> {
+        let utxos = self.utxos.read().unwrap();
+        let mut value_in: u64 = 0;
+        for input in inputs {
+            let output = utxos.get(input).ok_or(Error::UnknownInput(*input))?;
+            value_in = value_in
+                .checked_add(output.get_value())
+                .ok_or(Error::ValueOverflow)?;
+        }
+        Ok(value_in)
+    }
+
+    /// `inputs` and their value, or inputs picked with the default strategy if there are none.
+    fn get_or_select_inputs(
+        &self,
+        inputs: Vec<OutPoint>,
+        required: u64,
+    ) -> Result<(Vec<OutPoint>, u64), Error> {
+        if inputs.is_empty() {
+            let Selection { inputs, value_in } =
+                self.select_inputs(Strategy::default(), required)?;
+            Ok((inputs, value_in))
+        } else {
+            let value_in = self.get_inputs_value(&inputs)?;
+            Ok((inputs, value_in))
+        }
+    }
+
+    /// How many more blocks have to be connected before a block at `height` can spend `output`,
+    /// `None` if it already can. Outputs can be time locked, and coinbase outputs have to mature.
+    pub fn blocks_until_spendable(
+        &self,
+        outpoint: &OutPoint,
+        output: &Output<Thunder>,
+        height: u32,
+    ) -> Result<Option<u32>, Error> {
+        let mut cache = self.spendable_at.lock().unwrap();
+        if cache.0 != height {
+            *cache = (height, HashMap::new());
+        }
+        let spendable_at = match cache.1.get(outpoint) {
+            Some(spendable_at) => *spendable_at,
+            None => {
+                let spendable_at = self.get_spendable_at(outpoint, output)?;
+                cache.1.insert(*outpoint, spendable_at);
+                spendable_at
+            }
         };
-        self.update_utxos()?;
+        Ok(spendable_at
+            .filter(|spendable_at| height < *spendable_at)
+            .map(|spendable_at| spendable_at - height))
+    }
+
+    /// First height that can spend `output`, `None` if any height can.
+    fn get_spendable_at(
+        &self,
+        outpoint: &OutPoint,
+        output: &Output<Thunder>,
+    ) -> Result<Option<u32>, Error> {
+        let mut spendable_at = ThunderState::locked_until(output);
+        if let OutPoint::Coinbase { merkle_root, .. } = outpoint {
+            let index = self.index()?;
+            let txn = index.read_txn()?;
+            let coinbase_height = index
+                .get_coinbase_height(&txn, merkle_root)?
+                .ok_or(Error::UnknownCoinbase(*merkle_root))?;
+            let mature_at = ThunderState::coinbase_mature_at(coinbase_height);
+            spendable_at = Some(spendable_at.map_or(mature_at, |until| until.max(mature_at)));
+        }
+        Ok(spendable_at)
+    }
+
+    /// Check that a mainchain address belongs to the network that Thunder runs on.
+    pub fn check_main_address(
+        &self,
+        main_address: &bitcoin::Address<bitcoin::address::NetworkUnchecked>,
+    ) -> Result<(), Error> {
+        main_address.clone().require_network(self.network)?;
         Ok(())
     }
 
-    pub fn get_new_main_address(
+    pub async fn sign_and_send(&self, transaction: Transaction<Thunder>) -> Result<Txid, Error> {
+        for output in &transaction.outputs {
+            if let Content::Withdrawal { main_address, .. } = &output.content {
+                self.check_main_address(main_address)?;
+            }
+        }
+        let txid = transaction.txid();
+        let value_in: u64 = {
+            let utxos = self.wallet.get_utxos()?;
+            transaction
+                .inputs
+                .iter()
+                .filter_map(|input| utxos.get(input))
+                .map(GetValue::get_value)
+                .sum()
+        };
+        let value_out: u64 = transaction.outputs.iter().map(GetValue::get_value).sum();
+        let fee = value_in.saturating_sub(value_out);
+        if fee > self.max_fee {
+            return Err(Error::FeeTooHigh {
+                fee,
+                max_fee: self.max_fee,
+            });
+        }
+        self.policy.check(&transaction, fee)?;
+        let authorized_transaction = self.wallet.authorize(transaction)?;
+        self.node
+            .submit_transaction(&authorized_transaction)
+            .await?;
+        // The change address is used now, the next transaction gets a new one.
+        let mut change_address = self.change_address.lock().unwrap();
+        if authorized_transaction
+            .transaction
+            .outputs
+            .iter()
+            .any(|output| Some(output.address) == *change_address)
+        {
+            *change_address = None;
+        }
+        drop(change_address);
+        self.update_utxos()?;
+        Ok(txid)
+    }
+
+    pub async fn get_new_main_address(
         &self,
     ) -> Result<bitcoin::Address<bitcoin::address::NetworkChecked>, Error> {
         let address = self
-            .runtime
-            .block_on(self.miner.drivechain.client.getnewaddress("", "legacy"))?;
-        let address: bitcoin::Address<bitcoin::address::NetworkChecked> = address
-            .require_network(bitcoin::Network::Regtest)
-            .unwrap();
+            .miner
+            .lock()
+            .await
+            .drivechain
+            .client
+            .getnewaddress("", "legacy")
+            .await?;
+        let address: bitcoin::Address<bitcoin::address::NetworkChecked> =
+            address.require_network(self.network)?;
         Ok(address)
     }
 
-    const EMPTY_BLOCK_BMM_BRIBE: u64 = 1000;
-    pub fn mine(&mut self) -> Result<(), Error> {
-        self.runtime.block_on(async {
-            const NUM_TRANSACTIONS: usize = 1000;
-            let (transactions, fee) = self.node.get_transactions(NUM_TRANSACTIONS)?;
-            let coinbase = match fee {
-                0 => vec![],
-                _ => vec![ddk::types::Output {
-                    address: self.wallet.get_new_address()?,
-                    content: ddk::types::Content::Value(fee),
-                }],
-            };
-            let body = ddk::types::Body::new(transactions, coinbase);
-            let prev_side_hash = self.node.get_best_hash()?;
-            let prev_main_hash = self.miner.drivechain.get_mainchain_tip().await?;
-            let header = ddk::types::Header {
-                merkle_root: body.compute_merkle_root(),
-                prev_side_hash,
-                prev_main_hash,
-            };
-            let bribe = if fee > 0 {
-                fee
-            } else {
-                Self::EMPTY_BLOCK_BMM_BRIBE
-            };
-            let bribe = bitcoin::Amount::from_sat(bribe);
-            self.miner
-                .attempt_bmm(bribe.to_sat(), 0, header, body)
-                .await?;
-            self.miner.generate().await?;
-            if let Ok(Some((header, body))) = self.miner.confirm_bmm().await {
-                self.node.submit_block(&header, &body).await?;
+    /// Attempt to BMM a block, returns whether the block was confirmed and connected.
+    pub async fn mine(&self) -> Result<bool, Error> {
+        let result = self.try_mine().await;
+        let mut mining_stats = self.mining_stats.lock().unwrap();
+        mining_stats.attempts += 1;
+        match &result {
+            Ok(true) => {
+                mining_stats.successes += 1;
+                mining_stats.consecutive_failures = 0;
             }
+            Ok(false) => {
+                mining_stats.failures += 1;
+                mining_stats.consecutive_failures += 1;
+            }
+            Err(err) => {
+                mining_stats.failures += 1;
+                mining_stats.consecutive_failures += 1;
+                mining_stats.last_error = Some(format_error_chain(err));
+            }
+        }
+        result
+    }
 
-            Ok::<(), Error>(())
-        })?;
+    async fn try_mine(&self) -> Result<bool, Error> {
+        let BlockTemplate { transactions, info } = BlockTemplate::build(&self.node, &self.policy)?;
+        let fee = info.fees;
+        let consecutive_failures = self.mining_stats.lock().unwrap().consecutive_failures;
+        let (bribe, main_fee) = {
+            let bribe_config = self.bribe.lock().unwrap();
+            (
+                bribe_config.bribe(fee, consecutive_failures),
+                bribe_config.main_fee,
+            )
+        };
+        let coinbase = match fee {
+            0 => vec![],
+            _ => {
+                let miner_address = match self.coinbase.address {
+                    Some(address) => address,
+                    None => self.wallet.get_new_address()?,
+                };
+                self.coinbase.outputs(fee, bribe, miner_address)
+            }
+        };
+        let body = ddk::types::Body::new(transactions, coinbase);
+        let prev_side_hash = self.node.get_best_hash()?;
+        let mut miner = self.miner.lock().await;
+        let prev_main_hash = miner.drivechain.get_mainchain_tip().await?;
+        let header = ddk::types::Header {
+            merkle_root: body.compute_merkle_root(),
+            prev_side_hash,
+            prev_main_hash,
+        };
+        miner.attempt_bmm(bribe, main_fee, header, body).await?;
+        miner.generate().await?;
+        let mined = if let Ok(Some((header, body))) = miner.confirm_bmm().await {
+            self.node.submit_block(&header, &body).await?;
+            true
+        } else {
+            false
+        };
+        drop(miner);
         self.update_wallet()?;
         self.update_utxos()?;
+        Ok(mined)
+    }
+
+    /// Look up a confirmed transaction in the index.
+    pub fn get_transaction(
+        &self,
+        txid: &Txid,
+    ) -> Result<Option<(TransactionLocation, Transaction<Thunder>)>, Error> {
+        let Some(location) = self.index()?.get_transaction_location(txid)? else {
+            return Ok(None);
+        };
+        let Some(body) = self.node.get_body(location.height)? else {
+            return Ok(None);
+        };
+        let transaction = body.transactions.into_iter().nth(location.index as usize);
+        Ok(transaction.map(|transaction| (location, transaction)))
+    }
+
+    /// Value of a confirmed output, spent or not, `None` for deposits and outputs that are not
+    /// indexed.
+    fn get_spent_value(&self, outpoint: &OutPoint) -> Result<Option<u64>, Error> {
+        let output = match outpoint {
+            OutPoint::Regular { txid, vout } => self
+                .get_transaction(txid)?
+                .and_then(|(_, transaction)| transaction.outputs.into_iter().nth(*vout as usize)),
+            OutPoint::Coinbase { merkle_root, vout } => {
+                let index = self.index()?;
+                let txn = index.read_txn()?;
+                let coinbase_height = index.get_coinbase_height(&txn, merkle_root)?;
+                let body = match coinbase_height {
+                    Some(coinbase_height) => self.node.get_body(coinbase_height)?,
+                    None => None,
+                };
+                body.and_then(|body| body.coinbase.into_iter().nth(*vout as usize))
+            }
+            OutPoint::Deposit(_) => None,
+        };
+        Ok(output.map(|output| output.get_value()))
+    }
+
+    /// Fee rates in sats per byte to get confirmed within each of `fee_estimator::TARGETS`
+    /// blocks, from the fees paid in recent blocks and in the mempool. This reads old blocks,
+    /// call it through `run_blocking` from async code.
+    pub fn estimate_fees(&self) -> Result<Vec<FeeEstimate>, Error> {
+        let height = self.node.get_height()?;
+        let index_height = self.get_index_height()?;
+        let mut recent_blocks = vec![];
+        // Only the blocks that are still recent stay in the cache.
+        let mut cache = self.block_fees.lock().unwrap();
+        let mut block_fees = HashMap::new();
+        for height in height.saturating_sub(fee_estimator::RECENT_BLOCKS - 1)..=height {
+            let Some(header) = self.node.get_header(height)? else {
+                continue;
+            };
+            let hash = header.hash();
+            let fees = match cache.remove(&hash) {
+                Some(fees) => fees,
+                None => match self.get_block_fees(height)? {
+                    Some(fees) => fees,
+                    None => continue,
+                },
+            };
+            recent_blocks.push(fees.clone());
+            // The index can't resolve the inputs of blocks above it yet, so their fees leave out
+            // transactions and are computed again next time.
+            if matches!(index_height, Some(index_height) if height <= index_height) {
+                block_fees.insert(hash, fees);
+            }
+        }
+        *cache = block_fees;
+        drop(cache);
+        let mempool = block_template::get_mempool_fees(&self.node)?;
+        let next_height = height + 1;
+        let space = ThunderState::body_size_limit(next_height)
+            .saturating_sub(block_template::COINBASE_SIZE_RESERVE);
+        Ok(fee_estimator::estimate(
+            &recent_blocks,
+            &mempool,
+            space,
+            self.policy.min_fee_rate,
+        ))
+    }
+
+    /// Fees paid in the block at `height`, `None` if the node doesn't have its body.
+    fn get_block_fees(&self, height: u32) -> Result<Option<BlockFees>, Error> {
+        let Some(body) = self.node.get_body(height)? else {
+            return Ok(None);
+        };
+        let mut transactions = vec![];
+        'transactions: for transaction in &body.transactions {
+            let mut value_in = 0;
+            for input in &transaction.inputs {
+                let Some(value) = self.get_spent_value(input)? else {
+                    continue 'transactions;
+                };
+                value_in += value;
+            }
+            let value_out: u64 = transaction.outputs.iter().map(GetValue::get_value).sum();
+            let size = policy::estimate_size(transaction)?;
+            transactions.push((value_in.saturating_sub(value_out), size));
+        }
+        Ok(Some(BlockFees {
+            size: bincode::serialized_size(&body)?,
+            size_limit: ThunderState::body_size_limit(height),
+            transactions,
+        }))
+    }
+
+    /// Height of the last block that the index caught up with, `None` before the first one.
+    /// Record what `ThunderState` needs again from the bodies that the node already has, for
+    /// blocks that were connected before it recorded coinbase heights. Nothing is deleted from the
+    /// chain or downloaded again.
+    pub fn rebuild_state(&self, datadir: &Path) -> Result<(), Error> {
+        // heed hands out the environment that the node already opened.
+        let env = ddk::heed::EnvOpenOptions::new().open(datadir.join(NODE_DB))?;
+        let state = ThunderState::new(&env)?;
+        // Holding the write transaction keeps the node from connecting blocks meanwhile.
+        let mut txn = env.write_txn()?;
+        state.clear(&mut txn)?;
+        for height in 0..=self.node.get_height()? {
+            let Some(body) = self.node.get_body(height)? else {
+                break;
+            };
+            state.connect_coinbase(&mut txn, height, &body)?;
+        }
+        txn.commit()?;
         Ok(())
     }
 
-    fn update_wallet(&mut self) -> Result<(), Error> {
+    pub fn get_index_height(&self) -> Result<Option<u32>, Error> {
+        Ok(self.index()?.get_height()?)
+    }
+
+    /// Outputs ever paid to `address` in confirmed blocks, oldest first.
+    pub fn get_address_history(&self, address: &Address) -> Result<Vec<OutPoint>, Error> {
+        Ok(self.index()?.get_address_history(address)?)
+    }
+
+    /// Total value ever paid to `address` in connected blocks, and in deposits that the wallet
+    /// saw.
+    pub fn get_received_value(&self, address: &Address) -> Result<u64, Error> {
+        Ok(self.index()?.get_received_value(address)?)
+    }
+
+    fn update_wallet(&self) -> Result<(), Error> {
         let addresses = self.wallet.get_addresses()?;
-        let utxos = self.node.get_utxos_by_addresses(&addresses)?;
+        let mut utxos = self.node.get_utxos_by_addresses(&addresses)?;
+        // Deposits are not in any block, the index only learns about them from here.
+        self.index.record_deposits(&utxos)?;
+        utxos.retain(|_, output| !ThunderState::is_unspendable(output));
         let outpoints: Vec<_> = self.wallet.get_utxos()?.into_keys().collect();
         let spent = self.node.get_spent_utxos(&outpoints)?;
         self.wallet.put_utxos(&utxos)?;
@@ -143,7 +696,7 @@ impl App {
         Ok(())
     }
 
-    fn update_utxos(&mut self) -> Result<(), Error> {
+    fn update_utxos(&self) -> Result<(), Error> {
         let mut utxos = self.wallet.get_utxos()?;
         let transactions = self.node.get_all_transactions()?;
         for transaction in &transactions {
@@ -151,32 +704,44 @@ impl App {
                 utxos.remove(input);
             }
         }
-        self.utxos = utxos;
+        *self.utxos.write().unwrap() = utxos;
         Ok(())
     }
 
-    pub fn deposit(&mut self, amount: bitcoin::Amount, fee: bitcoin::Amount) -> Result<(), Error> {
-        self.runtime.block_on(async {
-            let address = self.wallet.get_new_address()?;
-            let address =
-                ddk::format_deposit_address(ThunderState::THIS_SIDECHAIN, &format!("{address}"));
-            self.miner
-                .drivechain
-                .client
-                .createsidechaindeposit(
-                    ThunderState::THIS_SIDECHAIN,
-                    &address,
-                    amount.into(),
-                    fee.into(),
-                )
-                .await?;
-            Ok(())
-        })
+    pub async fn deposit(
+        &self,
+        amount: bitcoin::Amount,
+        fee: bitcoin::Amount,
+    ) -> Result<(), Error> {
+        let address = self.wallet.get_new_address()?;
+        let address =
+            ddk::format_deposit_address(ThunderState::THIS_SIDECHAIN, &format!("{address}"));
+        self.miner
+            .lock()
+            .await
+            .drivechain
+            .client
+            .createsidechaindeposit(
+                ThunderState::THIS_SIDECHAIN,
+                &address,
+                amount.into(),
+                fee.into(),
+            )
+            .await?;
+        Ok(())
     }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error("bincode error")]
+    Bincode(#[from] bincode::Error),
+    #[error("transaction rejected by policy")]
+    Policy(#[from] policy::Error),
+    #[error("index error")]
+    Index(#[from] index::Error),
+    #[error("index is behind the node: {0}")]
+    IndexSync(String),
     #[error("node error")]
     Node(#[from] ddk::node::Error<thunder::Error>),
     #[error("wallet error")]
@@ -189,4 +754,24 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("jsonrpsee error")]
     Jsonrpsee(#[from] jsonrpsee::core::Error),
+    #[error("not enough funds, {available} sats available, {required} sats required")]
+    NotEnoughFunds { available: u64, required: u64 },
+    #[error("{0} is not an unspent wallet output")]
+    UnknownInput(OutPoint),
+    #[error("coinbase {0} is not indexed yet")]
+    UnknownCoinbase(MerkleRoot),
+    #[error("amount is out of range")]
+    ValueOverflow,
+    #[error("fee of {fee} sats is above the maximum of {max_fee} sats")]
+    FeeTooHigh { fee: u64, max_fee: u64 },
+    #[error("address error")]
+    Address(#[from] bitcoin::address::Error),
+    #[error("mainchain proxy error")]
+    MainProxy(#[from] main_proxy::Error),
+    #[error("heed error")]
+    Heed(#[from] ddk::heed::Error),
+    #[error("state error")]
+    State(#[from] thunder::Error),
+    #[error("background task was cancelled")]
+    TaskCancelled,
 }
@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+use crate::app::{App, Error};
+use tokio::sync::mpsc;
+
+/// How often the auto miner checks whether it is time to mine.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug)]
+pub struct AutoMinerConfig {
+    /// Mine a block at least this often.
+    pub interval: Duration,
+    /// Mine a block early once the mempool holds this many transactions.
+    pub mempool_threshold: Option<usize>,
+}
+
+/// Background task that keeps mining blocks, BMM results end up in `App::mining_stats`.
+pub struct AutoMiner {
+    task: tokio::task::JoinHandle<()>,
+    errors: mpsc::UnboundedReceiver<Error>,
+}
+
+impl AutoMiner {
+    pub fn start(app: &App, config: AutoMinerConfig) -> Self {
+        let (sender, errors) = mpsc::unbounded_channel();
+        let task = app.spawn(run(app.clone(), config, sender));
+        Self { task, errors }
+    }
+
+    /// Errors of the attempts that failed since the last call.
+    pub fn errors(&mut self) -> Vec<Error> {
+        let mut errors = vec![];
+        while let Ok(err) = self.errors.try_recv() {
+            errors.push(err);
+        }
+        errors
+    }
+
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+async fn run(app: App, config: AutoMinerConfig, errors: mpsc::UnboundedSender<Error>) {
+    let mut poll_interval = tokio::time::interval(POLL_INTERVAL);
+    let mut last_attempt = Instant::now();
+    loop {
+        poll_interval.tick().await;
+        let interval_elapsed = last_attempt.elapsed() >= config.interval;
+        let mempool_full = match config.mempool_threshold {
+            Some(mempool_threshold) => app
+                .node
+                .get_all_transactions()
+                .map(|transactions| transactions.len() >= mempool_threshold)
+                .unwrap_or(false),
+            None => false,
+        };
+        if !interval_elapsed && !mempool_full {
+            continue;
+        }
+        if let Err(err) = app.mine().await {
+            // Nobody might be listening, the error is in the mining stats either way.
+            let _ = errors.send(err);
+        }
+        last_attempt = Instant::now();
+    }
+}
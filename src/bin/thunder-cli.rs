@@ -0,0 +1,230 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use base64::Engine as _;
+use clap::{Parser, Subcommand};
+use ddk::bitcoin;
+use jsonrpsee::http_client::{HeaderMap, HeaderValue, HttpClientBuilder};
+use thunder::cli::{ConfigFile, DEFAULT_RPC_ADDR};
+use thunder::main_proxy::read_cookie;
+use thunder::rpc::{RpcClient, COOKIE_FILE_NAME};
+
+#[derive(Parser)]
+#[command(author, version, about = "Command line client for the Thunder RPC server", long_about = None)]
+struct Cli {
+    /// address of the Thunder node RPC server, defaults to rpc_addr from the node's config file,
+    /// then to 127.0.0.1:6000
+    #[arg(short, long, env = "THUNDER_RPC_ADDR")]
+    rpc_addr: Option<String>,
+    /// data directory of the node, its config file and RPC cookie are read from there, defaults
+    /// to ~/.local/share
+    #[arg(short, long, env = "THUNDER_DATADIR")]
+    datadir: Option<PathBuf>,
+    /// config file of the node, defaults to thunder.conf in the datadir
+    #[arg(short, long, env = "THUNDER_CONF")]
+    conf: Option<PathBuf>,
+    /// user to authenticate to the RPC server as
+    #[arg(long, env = "THUNDER_RPC_USER")]
+    rpc_user: Option<String>,
+    /// password to authenticate to the RPC server with
+    #[arg(long, env = "THUNDER_RPC_PASSWORD", hide_env_values = true)]
+    rpc_password: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Get the current block height
+    #[command(name = "getblockcount")]
+    GetBlockCount,
+    /// Get the header and body of the block at a height
+    #[command(name = "getblock")]
+    GetBlock { height: u32 },
+    /// Get a confirmed transaction and the height of its block
+    #[command(name = "gettransaction")]
+    GetTransaction {
+        #[arg(value_parser = parse_txid)]
+        txid: ddk::types::Txid,
+    },
+    /// List the outpoints of all confirmed outputs paid to an address
+    #[command(name = "getaddresshistory")]
+    GetAddressHistory { address: ddk::types::Address },
+    /// List wallet UTXOs that are not spent in the mempool
+    #[command(name = "listutxos")]
+    ListUtxos,
+    /// Generate a new wallet address
+    #[command(name = "getnewaddress")]
+    GetNewAddress,
+    /// Send BTC to a sidechain address
+    Send {
+        address: ddk::types::Address,
+        /// amount in BTC
+        #[arg(value_parser = parse_btc)]
+        amount: bitcoin::Amount,
+        /// sidechain transaction fee in BTC
+        #[arg(long, value_parser = parse_btc, default_value = "0.00001")]
+        fee: bitcoin::Amount,
+        /// wallet UTXO to spend, as printed by listutxos, repeat for more; picked from the
+        /// wallet if not given
+        #[arg(long = "input", value_parser = parse_outpoint)]
+        inputs: Vec<ddk::types::OutPoint>,
+    },
+    /// Withdraw BTC to a mainchain address
+    Withdraw {
+        main_address: bitcoin::Address<bitcoin::address::NetworkUnchecked>,
+        /// amount in BTC
+        #[arg(value_parser = parse_btc)]
+        amount: bitcoin::Amount,
+        /// mainchain fee in BTC
+        #[arg(value_parser = parse_btc)]
+        main_fee: bitcoin::Amount,
+        /// sidechain transaction fee in BTC
+        #[arg(long, value_parser = parse_btc, default_value = "0.00001")]
+        fee: bitcoin::Amount,
+        /// wallet UTXO to spend, as printed by listutxos, repeat for more; picked from the
+        /// wallet if not given
+        #[arg(long = "input", value_parser = parse_outpoint)]
+        inputs: Vec<ddk::types::OutPoint>,
+    },
+    /// Deposit BTC from the mainchain wallet to a new wallet address
+    Deposit {
+        /// amount in BTC
+        #[arg(value_parser = parse_btc)]
+        amount: bitcoin::Amount,
+        /// mainchain fee in BTC
+        #[arg(value_parser = parse_btc)]
+        fee: bitcoin::Amount,
+    },
+    /// Mine a block, prints whether BMM succeeded
+    Mine,
+    /// Get BMM attempt statistics
+    #[command(name = "getmininginfo")]
+    GetMiningInfo,
+    /// Estimate fee rates in sats per byte for confirmation within 1, 3 and 6 blocks
+    #[command(name = "estimatefees")]
+    EstimateFees,
+    /// Get the size, sigops and fees of the next block
+    #[command(name = "getblocktemplate")]
+    GetBlockTemplate,
+}
+
+fn parse_txid(txid: &str) -> anyhow::Result<ddk::types::Txid> {
+    let txid: [u8; 32] = hex::decode(txid)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("txid must be 32 bytes"))?;
+    Ok(txid.into())
+}
+
+fn parse_outpoint(outpoint: &str) -> serde_json::Result<ddk::types::OutPoint> {
+    serde_json::from_str(outpoint)
+}
+
+fn parse_btc(amount: &str) -> Result<bitcoin::Amount, bitcoin::amount::ParseAmountError> {
+    bitcoin::Amount::from_str_in(amount, bitcoin::Denomination::Bitcoin)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    // Settings are resolved the same way as the node's.
+    let (datadir, config_file) = ConfigFile::resolve(cli.datadir.as_deref(), cli.conf.as_deref())?;
+    let rpc_addr: SocketAddr = cli
+        .rpc_addr
+        .or(config_file.rpc_addr)
+        .unwrap_or(DEFAULT_RPC_ADDR.to_string())
+        .parse()?;
+    let rpc_user = cli.rpc_user.or(config_file.rpc_user);
+    let rpc_password = cli.rpc_password.or(config_file.rpc_password);
+    let (user, password) = match (rpc_user, rpc_password) {
+        (Some(user), Some(password)) => (user, password),
+        _ => read_cookie(&datadir.join(COOKIE_FILE_NAME))?,
+    };
+    let credentials =
+        base64::engine::general_purpose::STANDARD.encode(format!("{user}:{password}"));
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "authorization",
+        HeaderValue::from_str(&format!("Basic {credentials}"))?,
+    );
+    let client = HttpClientBuilder::default()
+        .set_headers(headers)
+        .build(format!("http://{rpc_addr}"))?;
+    match cli.command {
+        Command::GetBlockCount => {
+            let height = client.get_height().await?;
+            println!("{height}");
+        }
+        Command::GetBlock { height } => {
+            let header = client.get_header(height).await?;
+            let body = client.get_body(height).await?;
+            let block = serde_json::json!({ "header": header, "body": body });
+            println!("{}", serde_json::to_string_pretty(&block)?);
+        }
+        Command::GetTransaction { txid } => {
+            let transaction = client.get_transaction(txid).await?;
+            println!("{}", serde_json::to_string_pretty(&transaction)?);
+        }
+        Command::GetAddressHistory { address } => {
+            let history = client.get_address_history(address).await?;
+            println!("{}", serde_json::to_string_pretty(&history)?);
+        }
+        Command::ListUtxos => {
+            let utxos = client.get_utxos().await?;
+            println!("{}", serde_json::to_string_pretty(&utxos)?);
+        }
+        Command::GetNewAddress => {
+            let address = client.get_new_address().await?;
+            println!("{address}");
+        }
+        Command::Send {
+            address,
+            amount,
+            fee,
+            inputs,
+        } => {
+            let txid = client
+                .transfer(address, amount.to_sat(), fee.to_sat(), inputs)
+                .await?;
+            println!("{txid}");
+        }
+        Command::Withdraw {
+            main_address,
+            amount,
+            main_fee,
+            fee,
+            inputs,
+        } => {
+            let txid = client
+                .withdraw(
+                    main_address,
+                    amount.to_sat(),
+                    main_fee.to_sat(),
+                    fee.to_sat(),
+                    inputs,
+                )
+                .await?;
+            println!("{txid}");
+        }
+        Command::Deposit { amount, fee } => {
+            client.deposit(amount.to_sat(), fee.to_sat()).await?;
+        }
+        Command::Mine => {
+            let mined = client.mine().await?;
+            println!("{mined}");
+        }
+        Command::GetMiningInfo => {
+            let mining_stats = client.get_mining_stats().await?;
+            println!("{}", serde_json::to_string_pretty(&mining_stats)?);
+        }
+        Command::EstimateFees => {
+            let estimates = client.estimate_fees().await?;
+            println!("{}", serde_json::to_string_pretty(&estimates)?);
+        }
+        Command::GetBlockTemplate => {
+            let template = client.get_block_template().await?;
+            println!("{}", serde_json::to_string_pretty(&template)?);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::app::Error;
+use crate::policy::Policy;
+use crate::thunder::{Node, Thunder, ThunderState};
+use ddk::authorization::Authorization;
+use ddk::types::{AuthorizedTransaction, GetValue, OutPoint, Output};
+use serde::{Deserialize, Serialize};
+
+/// Body bytes kept free for the coinbase outputs and the body's own framing.
+pub const COINBASE_SIZE_RESERVE: u64 = 4 * 1024;
+
+struct Candidate {
+    transaction: AuthorizedTransaction<Authorization, Thunder>,
+    fee: u64,
+    size: u64,
+    sigops: u64,
+    /// Mempool transactions whose outputs this transaction spends.
+    parents: HashSet<usize>,
+}
+
+impl Candidate {
+    /// Compare fee rates without dividing, higher fee per byte first.
+    fn cmp_fee_rate(&self, other: &Self) -> std::cmp::Ordering {
+        let this = self.fee as u128 * other.size as u128;
+        let other = other.fee as u128 * self.size as u128;
+        other.cmp(&this)
+    }
+}
+
+/// Summary of a block template, as shown before mining.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TemplateInfo {
+    pub num_transactions: usize,
+    pub size: u64,
+    pub sigops: u64,
+    pub fees: u64,
+    pub size_limit: u64,
+    pub sigops_limit: u64,
+}
+
+/// Mempool transactions packed for the next block, ordered so that parents come before children.
+pub struct BlockTemplate {
+    pub transactions: Vec<AuthorizedTransaction<Authorization, Thunder>>,
+    pub info: TemplateInfo,
+}
+
+impl BlockTemplate {
+    /// Pack mempool transactions by fee rate, staying under the body size and sigops limits of
+    /// the next block. Transactions that don't meet the `policy` are left out.
+    pub fn build(node: &Node, policy: &Policy) -> Result<Self, Error> {
+        let height = node.get_height()? + 1;
+        let size_limit = ThunderState::body_size_limit(height);
+        let sigops_limit = ThunderState::body_sigops_limit(height);
+        let mut candidates = get_candidates(node, Some(policy))?;
+        let mut by_fee_rate: Vec<usize> = (0..candidates.len())
+            .filter(|index| candidates[*index].is_some())
+            .collect();
+        by_fee_rate.sort_by(|a, b| {
+            let (Some(a), Some(b)) = (&candidates[*a], &candidates[*b]) else {
+                unreachable!("only candidates are sorted");
+            };
+            a.cmp_fee_rate(b)
+        });
+        let mut info = TemplateInfo {
+            size_limit,
+            sigops_limit,
+            ..Default::default()
+        };
+        let mut included: HashSet<usize> = HashSet::new();
+        let mut spent: HashSet<OutPoint> = HashSet::new();
+        let mut order = vec![];
+        // Children that were skipped because their parents were not in the template yet are
+        // retried after every round that added something.
+        loop {
+            let mut progress = false;
+            for index in &by_fee_rate {
+                let Some(candidate) = &candidates[*index] else {
+                    continue;
+                };
+                if included.contains(index)
+                    || !candidate.parents.is_subset(&included)
+                    || info.size + candidate.size + COINBASE_SIZE_RESERVE > size_limit
+                    || info.sigops + candidate.sigops > sigops_limit
+                {
+                    continue;
+                }
+                let inputs = &candidate.transaction.transaction.inputs;
+                if inputs.iter().any(|input| spent.contains(input)) {
+                    continue;
+                }
+                spent.extend(inputs.iter().copied());
+                included.insert(*index);
+                order.push(*index);
+                info.size += candidate.size;
+                info.sigops += candidate.sigops;
+                info.fees += candidate.fee;
+                progress = true;
+            }
+            if !progress {
+                break;
+            }
+        }
+        info.num_transactions = order.len();
+        let transactions = order
+            .into_iter()
+            .filter_map(|index| candidates[index].take())
+            .map(|candidate| candidate.transaction)
+            .collect();
+        Ok(Self { transactions, info })
+    }
+}
+
+/// Fee and size in bytes of every mempool transaction with known inputs.
+pub fn get_mempool_fees(node: &Node) -> Result<Vec<(u64, u64)>, Error> {
+    let candidates = get_candidates(node, None)?;
+    Ok(candidates
+        .into_iter()
+        .flatten()
+        .map(|candidate| (candidate.fee, candidate.size))
+        .collect())
+}
+
+/// Mempool transactions with their fees, in mempool order. Transactions with unknown or
+/// overspent inputs, or that don't meet the policy, are `None`, and so can never be included, and
+/// neither can their children.
+fn get_candidates(node: &Node, policy: Option<&Policy>) -> Result<Vec<Option<Candidate>>, Error> {
+    let mempool = node.get_all_transactions()?;
+    let addresses: HashSet<_> = mempool
+        .iter()
+        .flat_map(|transaction| &transaction.authorizations)
+        .map(|authorization| ddk::authorization::get_address(&authorization.public_key))
+        .collect();
+    let confirmed = node.get_utxos_by_addresses(&addresses)?;
+    let mut unconfirmed: HashMap<OutPoint, (usize, Output<Thunder>)> = HashMap::new();
+    for (index, transaction) in mempool.iter().enumerate() {
+        let txid = transaction.transaction.txid();
+        for (vout, output) in transaction.transaction.outputs.iter().enumerate() {
+            let outpoint = OutPoint::Regular {
+                txid,
+                vout: vout as u32,
+            };
+            unconfirmed.insert(outpoint, (index, output.clone()));
+        }
+    }
+    let mut candidates = vec![];
+    for transaction in mempool {
+        let candidate = get_candidate(transaction, &confirmed, &unconfirmed)?.filter(|candidate| {
+            policy.map_or(true, |policy| {
+                policy
+                    .check(&candidate.transaction.transaction, candidate.fee)
+                    .is_ok()
+            })
+        });
+        candidates.push(candidate);
+    }
+    Ok(candidates)
+}
+
+fn get_candidate(
+    transaction: AuthorizedTransaction<Authorization, Thunder>,
+    confirmed: &HashMap<OutPoint, Output<Thunder>>,
+    unconfirmed: &HashMap<OutPoint, (usize, Output<Thunder>)>,
+) -> Result<Option<Candidate>, Error> {
+    let mut value_in: u64 = 0;
+    let mut parents = HashSet::new();
+    for input in &transaction.transaction.inputs {
+        if let Some(output) = confirmed.get(input) {
+            value_in += output.get_value();
+        } else if let Some((parent, output)) = unconfirmed.get(input) {
+            value_in += output.get_value();
+            parents.insert(*parent);
+        } else {
+            return Ok(None);
+        }
+    }
+    let value_out: u64 = transaction
+        .transaction
+        .outputs
+        .iter()
+        .map(GetValue::get_value)
+        .sum();
+    let Some(fee) = value_in.checked_sub(value_out) else {
+        return Ok(None);
+    };
+    let size = bincode::serialized_size(&transaction)?;
+    let sigops = ThunderState::transaction_sigops(&transaction.transaction);
+    Ok(Some(Candidate {
+        transaction,
+        fee,
+        size,
+        sigops,
+        parents,
+    }))
+}
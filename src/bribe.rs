@@ -0,0 +1,114 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// How the base BMM bribe is computed from the fees collected by a block, amounts are in sats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BribeStrategy {
+    /// Always bribe the same amount.
+    Fixed { amount: u64 },
+    /// Bribe a percentage of the collected fees, at most 100.
+    FeePercentage {
+        #[serde(deserialize_with = "deserialize_percent")]
+        percent: u64,
+    },
+    /// Bribe a percentage of the collected fees, at most 100, but never less than `floor`.
+    ///
+    /// When the block collects less than `floor` in fees, the miner pays the difference out of
+    /// its own pocket, so a floor above the fees means mining at a loss.
+    FeePercentageWithFloor {
+        #[serde(deserialize_with = "deserialize_percent")]
+        percent: u64,
+        floor: u64,
+    },
+    /// Bribe a percentage of the collected fees, at most 100, or `empty_block` when the block
+    /// collects no fees at all.
+    FeePercentageOrEmptyBlock {
+        #[serde(deserialize_with = "deserialize_percent")]
+        percent: u64,
+        empty_block: u64,
+    },
+}
+
+impl BribeStrategy {
+    fn base_bribe(&self, fees: u64) -> u64 {
+        match *self {
+            Self::Fixed { amount } => amount,
+            Self::FeePercentage { percent } => percent_of(fees, percent),
+            Self::FeePercentageWithFloor { percent, floor } => percent_of(fees, percent).max(floor),
+            Self::FeePercentageOrEmptyBlock {
+                percent,
+                empty_block,
+            } => {
+                if fees > 0 {
+                    percent_of(fees, percent)
+                } else {
+                    empty_block
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BribeConfig {
+    pub strategy: BribeStrategy,
+    /// Never bribe more than this.
+    pub max: Option<u64>,
+    /// Raise the bribe by this percentage for every consecutive failed BMM attempt.
+    pub escalation_percent: u64,
+    /// Mainchain fee for the BMM request.
+    pub main_fee: u64,
+}
+
+/// Give all collected fees to the mainchain miner, and 1000 sats for empty blocks.
+impl Default for BribeConfig {
+    fn default() -> Self {
+        Self {
+            strategy: BribeStrategy::FeePercentageOrEmptyBlock {
+                percent: 100,
+                empty_block: 1000,
+            },
+            max: None,
+            escalation_percent: 0,
+            main_fee: 0,
+        }
+    }
+}
+
+impl BribeConfig {
+    pub fn bribe(&self, fees: u64, consecutive_failures: u32) -> u64 {
+        let mut bribe = self.strategy.base_bribe(fees);
+        for _ in 0..consecutive_failures {
+            let raised = bribe.saturating_add(percent_of(bribe, self.escalation_percent));
+            if raised == bribe {
+                break;
+            }
+            bribe = raised;
+            if matches!(self.max, Some(max) if bribe >= max) {
+                break;
+            }
+        }
+        match self.max {
+            Some(max) => bribe.min(max),
+            None => bribe,
+        }
+    }
+}
+
+/// Bribing more than 100% of the fees would pay the mainchain miner more than the block earns.
+fn deserialize_percent<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let percent = u64::deserialize(deserializer)?;
+    if percent > 100 {
+        return Err(serde::de::Error::custom(format!(
+            "percent must be at most 100, got {percent}"
+        )));
+    }
+    Ok(percent)
+}
+
+fn percent_of(amount: u64, percent: u64) -> u64 {
+    (amount as u128 * percent as u128 / 100)
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
@@ -1,66 +1,323 @@
+use crate::auto_miner::AutoMinerConfig;
+use crate::bribe::BribeConfig;
+use crate::coinbase::CoinbaseConfig;
+use crate::params::Schedule;
+use crate::policy::Policy;
 use clap::Parser;
-use std::{net::SocketAddr, path::PathBuf};
+use ddk::bitcoin;
+use serde::Deserialize;
+use std::{net::SocketAddr, path::Path, path::PathBuf, time::Duration};
 
+/// Shared with `thunder-cli`, so that it connects to the node's RPC server by default.
+pub const DEFAULT_RPC_ADDR: &str = "127.0.0.1:6000";
+
+/// Name of the config file that is read from the datadir when `--conf` is not given.
+const CONFIG_FILE_NAME: &str = "thunder.conf";
+
+/// Mainchain network that the sidechain is attached to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Regtest,
+    Signet,
+    Testnet,
+    Mainnet,
+}
+
+impl Network {
+    /// Default port of the mainchain node RPC server on this network.
+    pub fn default_main_port(self) -> u16 {
+        match self {
+            Self::Regtest => 18443,
+            Self::Signet => 38332,
+            Self::Testnet => 18332,
+            Self::Mainnet => 8332,
+        }
+    }
+}
+
+impl From<Network> for bitcoin::Network {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Regtest => Self::Regtest,
+            Network::Signet => Self::Signet,
+            Network::Testnet => Self::Testnet,
+            Network::Mainnet => Self::Bitcoin,
+        }
+    }
+}
+
+/// Settings are resolved in the following order: command line flag, environment variable, config
+/// file, default.
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     /// data directory for storing blockchain data and wallet, defaults to ~/.local/share
-    #[arg(short, long)]
+    #[arg(short, long, env = "THUNDER_DATADIR")]
     pub datadir: Option<PathBuf>,
+    /// TOML config file, defaults to thunder.conf in the datadir
+    #[arg(short, long, env = "THUNDER_CONF")]
+    pub conf: Option<PathBuf>,
+    /// mainchain network, defaults to regtest
+    #[arg(long, value_enum, env = "THUNDER_NETWORK")]
+    pub network: Option<Network>,
     /// address to use for P2P networking, defaults to 127.0.0.1:4000
-    #[arg(short, long)]
+    #[arg(short, long, env = "THUNDER_NET_ADDR")]
     pub net_addr: Option<String>,
-    /// address to connect to mainchain node RPC server, defaults to 127.0.0.1:18443
-    #[arg(short, long)]
+    /// address to connect to mainchain node RPC server, defaults to 127.0.0.1 with the network's
+    /// RPC port, 18443 for regtest
+    #[arg(short, long, env = "THUNDER_MAIN_ADDR")]
     pub main_addr: Option<String>,
     /// mainchain node RPC user, defaults to "user"
-    #[arg(short, long)]
+    #[arg(short, long, env = "THUNDER_MAIN_USER")]
     pub user_main: Option<String>,
     /// mainchain node RPC password, defaults to "password"
-    #[arg(short, long)]
+    #[arg(short, long, env = "THUNDER_MAIN_PASSWORD", hide_env_values = true)]
     pub password_main: Option<String>,
+    /// mainchain node RPC cookie file, used instead of the user and password if set
+    #[arg(long, env = "THUNDER_MAIN_COOKIE")]
+    pub main_cookie: Option<PathBuf>,
+    /// TOML chain spec with the consensus limits by activation height, defaults to the built in
+    /// limits
+    #[arg(long, env = "THUNDER_CHAIN_SPEC")]
+    pub chain_spec: Option<PathBuf>,
+    /// address to serve the JSON-RPC API on, defaults to 127.0.0.1:6000
+    #[arg(short, long, env = "THUNDER_RPC_ADDR")]
+    pub rpc_addr: Option<String>,
+    /// user that RPC clients have to authenticate as, together with --rpc-password; without them
+    /// a random password is written to the .cookie file in the datadir on every start
+    #[arg(long, env = "THUNDER_RPC_USER")]
+    pub rpc_user: Option<String>,
+    /// password that RPC clients have to authenticate with
+    #[arg(long, env = "THUNDER_RPC_PASSWORD", hide_env_values = true)]
+    pub rpc_password: Option<String>,
+    /// run the node without the GUI, until SIGINT or SIGTERM is received
+    #[arg(long)]
+    pub headless: bool,
+    /// in headless mode, mine a block every this many seconds
+    #[arg(long, requires = "headless", value_name = "SECONDS")]
+    pub auto_mine: Option<u64>,
+    /// with --auto-mine, also mine as soon as the mempool holds this many transactions
+    #[arg(long, requires = "auto_mine", value_name = "TRANSACTIONS")]
+    pub auto_mine_mempool: Option<usize>,
+    /// refuse to send transactions that pay more than this in fees, defaults to 100000
+    #[arg(long, env = "THUNDER_MAX_FEE", value_name = "SATS")]
+    pub max_fee: Option<u64>,
+    /// rebuild the index and the coinbase heights that consensus needs from the blocks that the
+    /// node already has, nothing is downloaded again
+    #[arg(long)]
+    pub reindex: bool,
+    /// print the resolved configuration and exit
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+/// Contents of `thunder.conf`, every field is optional.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub datadir: Option<PathBuf>,
+    pub network: Option<Network>,
+    pub net_addr: Option<String>,
+    pub main_addr: Option<String>,
+    pub main_user: Option<String>,
+    pub main_password: Option<String>,
+    pub main_cookie: Option<PathBuf>,
+    pub chain_spec: Option<PathBuf>,
+    pub rpc_addr: Option<String>,
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    pub max_fee: Option<u64>,
+    pub bribe: Option<BribeConfig>,
+    pub coinbase: Option<CoinbaseConfig>,
+    pub policy: Option<Policy>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let config_file = toml::from_str(&contents)?;
+        Ok(config_file)
+    }
+
+    /// Resolve the datadir from `--datadir`, the config file and the default, and load the config
+    /// file from `--conf` or the datadir. Shared with `thunder-cli`, so that it finds the node's
+    /// settings the same way the node does.
+    pub fn resolve(datadir: Option<&Path>, conf: Option<&Path>) -> anyhow::Result<(PathBuf, Self)> {
+        // An explicitly passed config file must exist, and it can set the datadir.
+        let explicit_config_file = match conf {
+            Some(conf) => Some(Self::load(conf)?),
+            None => None,
+        };
+        let datadir = datadir
+            .map(Path::to_path_buf)
+            .or_else(|| explicit_config_file.as_ref()?.datadir.clone())
+            .unwrap_or_else(|| {
+                dirs::data_dir().expect("couldn't get default datadir, specify --datadir")
+            })
+            .join("thunder");
+        let config_file = match explicit_config_file {
+            Some(config_file) => config_file,
+            None => {
+                let path = datadir.join(CONFIG_FILE_NAME);
+                if path.exists() {
+                    Self::load(&path)?
+                } else {
+                    Self::default()
+                }
+            }
+        };
+        Ok((datadir, config_file))
+    }
 }
 
 pub struct Config {
     pub datadir: PathBuf,
+    pub network: bitcoin::Network,
     pub net_addr: SocketAddr,
     pub main_addr: SocketAddr,
     pub main_user: String,
     pub main_password: String,
+    /// Cookie file to read the mainchain RPC user and password from, overrides `main_user` and
+    /// `main_password`.
+    pub main_cookie: Option<PathBuf>,
+    pub chain_spec: Option<PathBuf>,
+    /// Consensus limits loaded from `chain_spec`.
+    pub schedule: Schedule,
+    pub rpc_addr: SocketAddr,
+    /// Credentials for the RPC server, a cookie file is written if they are not set.
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    /// Highest fee in sats that the wallet pays for a transaction.
+    pub max_fee: u64,
+    /// Initial BMM bribe strategy, it can be changed at runtime from the GUI.
+    pub bribe: BribeConfig,
+    /// How block fees are paid out in the coinbase of mined blocks.
+    pub coinbase: CoinbaseConfig,
+    /// Which transactions this node sends and mines.
+    pub policy: Policy,
+}
+
+/// Formats the config as TOML that can be used as `thunder.conf`, with the passwords commented out.
+impl std::fmt::Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // `datadir` is the directory that `thunder` gets appended to, as it is configured.
+        if let Some(datadir) = self.datadir.parent() {
+            writeln!(f, "datadir = {datadir:?}")?;
+        }
+        let network = match self.network {
+            bitcoin::Network::Bitcoin => "mainnet".to_string(),
+            network => network.to_string(),
+        };
+        writeln!(f, "network = \"{network}\"")?;
+        writeln!(f, "net_addr = \"{}\"", self.net_addr)?;
+        writeln!(f, "main_addr = \"{}\"", self.main_addr)?;
+        writeln!(f, "main_user = {:?}", self.main_user)?;
+        // The passwords are left for the reader to fill in.
+        writeln!(f, "# main_password = \"...\"")?;
+        if let Some(main_cookie) = &self.main_cookie {
+            writeln!(f, "main_cookie = {main_cookie:?}")?;
+        }
+        if let Some(chain_spec) = &self.chain_spec {
+            writeln!(f, "chain_spec = {chain_spec:?}")?;
+        }
+        writeln!(f, "rpc_addr = \"{}\"", self.rpc_addr)?;
+        if let Some(rpc_user) = &self.rpc_user {
+            writeln!(f, "rpc_user = {rpc_user:?}")?;
+            writeln!(f, "# rpc_password = \"...\"")?;
+        }
+        writeln!(f, "max_fee = {}", self.max_fee)?;
+        let mut tables = toml::Table::new();
+        let bribe = toml::Value::try_from(&self.bribe).map_err(|_| std::fmt::Error)?;
+        tables.insert("bribe".into(), bribe);
+        let coinbase = toml::Value::try_from(&self.coinbase).map_err(|_| std::fmt::Error)?;
+        tables.insert("coinbase".into(), coinbase);
+        let policy = toml::Value::try_from(&self.policy).map_err(|_| std::fmt::Error)?;
+        tables.insert("policy".into(), policy);
+        let tables = toml::to_string(&tables).map_err(|_| std::fmt::Error)?;
+        write!(f, "\n{tables}")
+    }
 }
 
 impl Cli {
+    pub fn get_auto_miner_config(&self) -> Option<AutoMinerConfig> {
+        let interval = self.auto_mine?;
+        Some(AutoMinerConfig {
+            interval: Duration::from_secs(interval),
+            mempool_threshold: self.auto_mine_mempool,
+        })
+    }
+
     pub fn get_config(&self) -> anyhow::Result<Config> {
+        let (datadir, config_file) =
+            ConfigFile::resolve(self.datadir.as_deref(), self.conf.as_deref())?;
+        let network = self.network.or(config_file.network).unwrap_or_default();
         const DEFAULT_NET_ADDR: &str = "127.0.0.1:4000";
         let net_addr: SocketAddr = self
             .net_addr
             .clone()
+            .or(config_file.net_addr)
             .unwrap_or(DEFAULT_NET_ADDR.to_string())
             .parse()?;
-        const DEFAULT_MAIN_ADDR: &str = "127.0.0.1:18443";
         let main_addr: SocketAddr = self
             .main_addr
             .clone()
-            .unwrap_or(DEFAULT_MAIN_ADDR.to_string())
+            .or(config_file.main_addr)
+            .unwrap_or(format!("127.0.0.1:{}", network.default_main_port()))
             .parse()?;
-        let datadir = self
-            .datadir
+        let rpc_addr: SocketAddr = self
+            .rpc_addr
             .clone()
-            .unwrap_or_else(|| {
-                dirs::data_dir().expect("couldn't get default datadir, specify --datadir")
-            })
-            .join("thunder");
-        let main_user = self.user_main.clone().unwrap_or_else(|| "user".into());
+            .or(config_file.rpc_addr)
+            .unwrap_or(DEFAULT_RPC_ADDR.to_string())
+            .parse()?;
+        let rpc_user = self.rpc_user.clone().or(config_file.rpc_user);
+        let rpc_password = self.rpc_password.clone().or(config_file.rpc_password);
+        if rpc_user.is_some() != rpc_password.is_some() {
+            anyhow::bail!("rpc_user and rpc_password have to be set together");
+        }
+        let main_user = self
+            .user_main
+            .clone()
+            .or(config_file.main_user)
+            .unwrap_or_else(|| "user".into());
         let main_password = self
             .password_main
             .clone()
+            .or(config_file.main_password)
             .unwrap_or_else(|| "password".into());
+        let main_cookie = self.main_cookie.clone().or(config_file.main_cookie);
+        let chain_spec = self.chain_spec.clone().or(config_file.chain_spec);
+        let schedule = match &chain_spec {
+            Some(chain_spec) => Schedule::load(chain_spec)?,
+            None => Schedule::default(),
+        };
+        const DEFAULT_MAX_FEE: u64 = 100_000;
+        let max_fee = self
+            .max_fee
+            .or(config_file.max_fee)
+            .unwrap_or(DEFAULT_MAX_FEE);
+        let bribe = config_file.bribe.unwrap_or_default();
+        let coinbase = config_file.coinbase.unwrap_or_default();
+        let policy = config_file.policy.unwrap_or_default();
         Ok(Config {
             datadir,
+            network: network.into(),
             net_addr,
             main_addr,
             main_user,
             main_password,
+            main_cookie,
+            chain_spec,
+            schedule,
+            rpc_addr,
+            rpc_user,
+            rpc_password,
+            max_fee,
+            bribe,
+            coinbase,
+            policy,
         })
     }
 }
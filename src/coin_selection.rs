@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use crate::thunder::Thunder;
+use ddk::types::{Address, GetValue, OutPoint, Output};
+use serde::{Deserialize, Serialize};
+
+/// Give up on finding an exact match after this many branch and bound steps.
+const BNB_MAX_TRIES: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Spend the biggest UTXOs first, uses the fewest inputs.
+    LargestFirst,
+    /// Look for inputs that add up to the target closely enough to not need change, and fall back
+    /// to largest first if there are none.
+    #[default]
+    BranchAndBound,
+    /// Avoid linking addresses: spend a single UTXO if one is big enough, otherwise spend whole
+    /// addresses at a time, so that as few addresses as possible end up in one transaction.
+    Privacy,
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LargestFirst => write!(f, "largest first"),
+            Self::BranchAndBound => write!(f, "branch and bound"),
+            Self::Privacy => write!(f, "privacy"),
+        }
+    }
+}
+
+impl Strategy {
+    pub const ALL: [Self; 3] = [Self::LargestFirst, Self::BranchAndBound, Self::Privacy];
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub inputs: Vec<OutPoint>,
+    pub value_in: u64,
+}
+
+/// Pick UTXOs worth at least `target` sats, `None` if they are not enough. Branch and bound
+/// accepts up to `change_tolerance` sats over the target, which then goes to the fee instead of a
+/// change output.
+pub fn select(
+    strategy: Strategy,
+    utxos: &[(OutPoint, Output<Thunder>)],
+    target: u64,
+    change_tolerance: u64,
+) -> Option<Selection> {
+    let mut utxos: Vec<_> = utxos
+        .iter()
+        .map(|(outpoint, output)| (*outpoint, output.address, output.get_value()))
+        .filter(|(_, _, value)| *value > 0)
+        .collect();
+    utxos.sort_by_key(|(outpoint, _, value)| (std::cmp::Reverse(*value), format!("{outpoint}")));
+    match strategy {
+        Strategy::LargestFirst => largest_first(&utxos, target),
+        Strategy::BranchAndBound => branch_and_bound(&utxos, target, change_tolerance)
+            .or_else(|| largest_first(&utxos, target)),
+        Strategy::Privacy => privacy(&utxos, target),
+    }
+}
+
+/// `utxos` are sorted by value, biggest first.
+fn largest_first(utxos: &[(OutPoint, Address, u64)], target: u64) -> Option<Selection> {
+    let mut selection = Selection {
+        inputs: vec![],
+        value_in: 0,
+    };
+    for (outpoint, _, value) in utxos {
+        if selection.value_in >= target {
+            break;
+        }
+        selection.inputs.push(*outpoint);
+        selection.value_in += value;
+    }
+    (selection.value_in >= target).then_some(selection)
+}
+
+/// Depth first search over including or leaving out each UTXO, biggest first, for a selection
+/// worth between `target` and `target + change_tolerance`. `utxos` are sorted by value, biggest
+/// first.
+fn branch_and_bound(
+    utxos: &[(OutPoint, Address, u64)],
+    target: u64,
+    change_tolerance: u64,
+) -> Option<Selection> {
+    let upper_bound = target.saturating_add(change_tolerance);
+    // Value of all UTXOs from an index on, to cut branches that can't reach the target anymore.
+    let mut remaining = vec![0; utxos.len() + 1];
+    for (index, (_, _, value)) in utxos.iter().enumerate().rev() {
+        remaining[index] = remaining[index + 1] + value;
+    }
+    let mut included = vec![false; utxos.len()];
+    let mut best: Option<(u64, Vec<bool>)> = None;
+    let mut value = 0;
+    let mut index = 0;
+    let mut tries = 0;
+    loop {
+        tries += 1;
+        let backtrack = if value > upper_bound || value + remaining[index] < target {
+            true
+        } else if value >= target {
+            if best
+                .as_ref()
+                .map_or(true, |(best_value, _)| value < *best_value)
+            {
+                best = Some((value, included.clone()));
+            }
+            true
+        } else {
+            false
+        };
+        if tries > BNB_MAX_TRIES {
+            break;
+        }
+        if backtrack {
+            // Go back to the last included UTXO and try leaving it out instead.
+            let Some(last) = included[..index].iter().rposition(|included| *included) else {
+                break;
+            };
+            included[last] = false;
+            value -= utxos[last].2;
+            index = last + 1;
+        } else {
+            included[index] = true;
+            value += utxos[index].2;
+            index += 1;
+        }
+        if best
+            .as_ref()
+            .map_or(false, |(best_value, _)| *best_value == target)
+        {
+            break;
+        }
+    }
+    let (value_in, included) = best?;
+    let inputs = utxos
+        .iter()
+        .zip(included)
+        .filter(|(_, included)| *included)
+        .map(|((outpoint, _, _), _)| *outpoint)
+        .collect();
+    Some(Selection { inputs, value_in })
+}
+
+/// `utxos` are sorted by value, biggest first.
+fn privacy(utxos: &[(OutPoint, Address, u64)], target: u64) -> Option<Selection> {
+    if let Some((outpoint, _, value)) = utxos.iter().rev().find(|(_, _, value)| *value >= target) {
+        return Some(Selection {
+            inputs: vec![*outpoint],
+            value_in: *value,
+        });
+    }
+    let mut addresses: HashMap<Address, (u64, Vec<OutPoint>)> = HashMap::new();
+    for (outpoint, address, value) in utxos {
+        let (address_value, outpoints) = addresses.entry(*address).or_default();
+        *address_value += value;
+        outpoints.push(*outpoint);
+    }
+    let mut addresses: Vec<_> = addresses.into_values().collect();
+    addresses.sort_by_key(|(value, outpoints)| (std::cmp::Reverse(*value), outpoints.len()));
+    let mut selection = Selection {
+        inputs: vec![],
+        value_in: 0,
+    };
+    for (value, outpoints) in addresses {
+        if selection.value_in >= target {
+            break;
+        }
+        selection.inputs.extend(outpoints);
+        selection.value_in += value;
+    }
+    (selection.value_in >= target).then_some(selection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ddk::types::{Content, Txid};
+
+    fn utxos(values: &[u64]) -> Vec<(OutPoint, Output<Thunder>)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| {
+                let outpoint = OutPoint::Regular {
+                    txid: Txid::from([0; 32]),
+                    vout: vout as u32,
+                };
+                let output = Output {
+                    address: Address::from([vout as u8; 20]),
+                    content: Content::Value(*value),
+                };
+                (outpoint, output)
+            })
+            .collect()
+    }
+
+    fn values(utxos: &[(OutPoint, Output<Thunder>)], selection: &Selection) -> Vec<u64> {
+        let mut values: Vec<_> = selection
+            .inputs
+            .iter()
+            .map(|input| {
+                let (_, output) = utxos
+                    .iter()
+                    .find(|(outpoint, _)| outpoint == input)
+                    .unwrap();
+                output.get_value()
+            })
+            .collect();
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn branch_and_bound_finds_exact_match() {
+        let utxos = utxos(&[10_000, 7_000, 5_000, 3_000, 2_000]);
+        let selection = select(Strategy::BranchAndBound, &utxos, 9_000, 0).unwrap();
+        assert_eq!(selection.value_in, 9_000);
+        assert_eq!(values(&utxos, &selection).iter().sum::<u64>(), 9_000);
+        // Largest first overshoots the same target.
+        let largest_first = select(Strategy::LargestFirst, &utxos, 9_000, 0).unwrap();
+        assert_eq!(largest_first.value_in, 10_000);
+    }
+
+    #[test]
+    fn branch_and_bound_falls_back_to_largest_first() {
+        // No combination lands in 6_500..=6_600, so there is no changeless selection.
+        let utxos = utxos(&[4_000, 3_000, 1_000]);
+        let selection = select(Strategy::BranchAndBound, &utxos, 6_500, 100).unwrap();
+        assert_eq!(
+            selection,
+            select(Strategy::LargestFirst, &utxos, 6_500, 100).unwrap()
+        );
+        assert_eq!(values(&utxos, &selection), vec![3_000, 4_000]);
+    }
+
+    #[test]
+    fn insufficient_funds_select_nothing() {
+        let utxos = utxos(&[4_000, 3_000, 1_000]);
+        for strategy in Strategy::ALL {
+            assert_eq!(select(strategy, &utxos, 8_001, 0), None);
+        }
+        assert_eq!(select(Strategy::LargestFirst, &[], 1, 0), None);
+    }
+}
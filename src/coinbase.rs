@@ -0,0 +1,201 @@
+use crate::thunder::Thunder;
+use ddk::types::{Address, Content, Output};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Payout {
+    #[serde(with = "address_string")]
+    pub address: Address,
+    pub weight: u64,
+}
+
+/// What happens to the block fees that are left once the miner got the BMM bribe back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeftoverRule {
+    /// The miner keeps everything.
+    Miner,
+    /// The leftover is split between the payouts by weight, rounding dust goes to the miner.
+    Payouts,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoinbaseConfig {
+    /// Miner reward address, a fresh wallet address is used for every block if not set.
+    #[serde(with = "address_string::option")]
+    pub address: Option<Address>,
+    pub payouts: Vec<Payout>,
+    /// Defaults to `Payouts` if there are any, and to `Miner` otherwise.
+    pub leftover: Option<LeftoverRule>,
+}
+
+impl CoinbaseConfig {
+    pub fn leftover_rule(&self) -> LeftoverRule {
+        match self.leftover {
+            Some(leftover) => leftover,
+            None if self.payouts.is_empty() => LeftoverRule::Miner,
+            None => LeftoverRule::Payouts,
+        }
+    }
+
+    /// Split the block `fees` into coinbase outputs, the miner is paid back the `bribe` first.
+    pub fn outputs(&self, fees: u64, bribe: u64, miner_address: Address) -> Vec<Output<Thunder>> {
+        let reimbursement = bribe.min(fees);
+        let leftover = fees - reimbursement;
+        // Weights come from the config file, their sum doesn't have to fit into a u64.
+        let total_weight: u128 = self
+            .payouts
+            .iter()
+            .map(|payout| payout.weight as u128)
+            .sum();
+        let payout_values = match self.leftover_rule() {
+            // Every share is at most `leftover`, so it fits into a u64, and rounding down makes
+            // the shares add up to at most `leftover`.
+            LeftoverRule::Payouts if total_weight > 0 => self
+                .payouts
+                .iter()
+                .map(|payout| (leftover as u128 * payout.weight as u128 / total_weight) as u64)
+                .collect(),
+            _ => vec![0; self.payouts.len()],
+        };
+        let miner_value = fees - payout_values.iter().sum::<u64>();
+        let miner_output = (miner_address, miner_value);
+        let payout_outputs = self
+            .payouts
+            .iter()
+            .map(|payout| payout.address)
+            .zip(payout_values);
+        std::iter::once(miner_output)
+            .chain(payout_outputs)
+            .filter(|(_, value)| *value > 0)
+            .map(|(address, value)| Output {
+                address,
+                content: Content::Value(value),
+            })
+            .collect()
+    }
+}
+
+/// Addresses are written as strings in config files.
+mod address_string {
+    use ddk::types::Address;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(address: &Address, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(address)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Address, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        address.parse().map_err(serde::de::Error::custom)
+    }
+
+    pub mod option {
+        use ddk::types::Address;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            address: &Option<Address>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match address {
+                Some(address) => super::serialize(address, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Address>, D::Error> {
+            let Some(address) = Option::<String>::deserialize(deserializer)? else {
+                return Ok(None);
+            };
+            address.parse().map(Some).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINER: [u8; 20] = [0; 20];
+
+    fn config(weights: &[u64], leftover: Option<LeftoverRule>) -> CoinbaseConfig {
+        CoinbaseConfig {
+            address: None,
+            payouts: weights
+                .iter()
+                .enumerate()
+                .map(|(i, weight)| Payout {
+                    address: Address::from([i as u8 + 1; 20]),
+                    weight: *weight,
+                })
+                .collect(),
+            leftover,
+        }
+    }
+
+    fn values(outputs: &[Output<Thunder>]) -> Vec<(Address, u64)> {
+        outputs
+            .iter()
+            .map(|output| match output.content {
+                Content::Value(value) => (output.address, value),
+                _ => panic!("coinbase output without a value"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rounding_dust_goes_to_the_miner() {
+        let outputs = config(&[1, 1, 1], None).outputs(1000, 100, Address::from(MINER));
+        // 900 left over, 300 each, nothing to round.
+        assert_eq!(
+            values(&outputs),
+            vec![
+                (Address::from(MINER), 100),
+                (Address::from([1; 20]), 300),
+                (Address::from([2; 20]), 300),
+                (Address::from([3; 20]), 300),
+            ]
+        );
+        let outputs = config(&[1, 1, 1], None).outputs(1001, 100, Address::from(MINER));
+        // 901 left over, 300 each and the remaining 1 sat goes to the miner.
+        assert_eq!(values(&outputs)[0], (Address::from(MINER), 101));
+        let total: u64 = values(&outputs).iter().map(|(_, value)| value).sum();
+        assert_eq!(total, 1001);
+    }
+
+    #[test]
+    fn zero_payouts_are_left_out() {
+        let outputs = config(&[1, 1000], None).outputs(500, 0, Address::from(MINER));
+        // The first payout rounds down to 0 and gets no output.
+        assert_eq!(
+            values(&outputs),
+            vec![(Address::from(MINER), 1), (Address::from([2; 20]), 499)]
+        );
+    }
+
+    #[test]
+    fn huge_weights_do_not_overflow() {
+        let outputs =
+            config(&[u64::MAX, u64::MAX], None).outputs(u64::MAX, 0, Address::from(MINER));
+        let values = values(&outputs);
+        assert_eq!(values[1].1, u64::MAX / 2);
+        assert_eq!(values[2].1, u64::MAX / 2);
+        let total: u128 = values.iter().map(|(_, value)| *value as u128).sum();
+        assert_eq!(total, u64::MAX as u128);
+    }
+
+    #[test]
+    fn miner_keeps_everything_without_payout_rule() {
+        let outputs =
+            config(&[1, 1], Some(LeftoverRule::Miner)).outputs(1000, 100, Address::from(MINER));
+        assert_eq!(values(&outputs), vec![(Address::from(MINER), 1000)]);
+        // A bribe above the fees is only paid back up to the fees.
+        let outputs = config(&[1], None).outputs(50, 100, Address::from(MINER));
+        assert_eq!(values(&outputs), vec![(Address::from(MINER), 50)]);
+    }
+}
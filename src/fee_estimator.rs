@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+/// Confirmation targets in blocks that fees are estimated for.
+pub const TARGETS: [u32; 3] = [1, 3, 6];
+
+/// How many of the most recent blocks are looked at.
+pub const RECENT_BLOCKS: u32 = 12;
+
+/// Blocks that use less than this percentage of the body size limit had room for any fee.
+const FULL_BLOCK_PERCENT: u64 = 90;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FeeEstimate {
+    /// Confirm within this many blocks.
+    pub target: u32,
+    /// Sats per byte.
+    pub fee_rate: u64,
+}
+
+/// Fees paid in a recent block.
+#[derive(Clone, Debug)]
+pub struct BlockFees {
+    pub size: u64,
+    pub size_limit: u64,
+    /// Fee and size in bytes of every transaction with known inputs.
+    pub transactions: Vec<(u64, u64)>,
+}
+
+impl BlockFees {
+    /// The lowest fee rate that got into this block, 0 if the block wasn't full.
+    fn min_fee_rate(&self) -> u64 {
+        if self.size * 100 < self.size_limit * FULL_BLOCK_PERCENT {
+            return 0;
+        }
+        self.transactions
+            .iter()
+            .map(|(fee, size)| fee_rate(*fee, *size))
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+fn fee_rate(fee: u64, size: u64) -> u64 {
+    let size = size.max(1);
+    fee / size + u64::from(fee % size != 0)
+}
+
+/// Estimate fee rates for each of the `TARGETS`, never below `min_fee_rate`.
+///
+/// Recent blocks give the rate that would have made it into at least one in `target` of them.
+/// The mempool gives the rate that outbids everything that doesn't fit into `target` blocks of
+/// `space` bytes. The higher of the two is used.
+pub fn estimate(
+    recent_blocks: &[BlockFees],
+    mempool: &[(u64, u64)],
+    space: u64,
+    min_fee_rate: u64,
+) -> Vec<FeeEstimate> {
+    let mut block_rates: Vec<u64> = recent_blocks.iter().map(BlockFees::min_fee_rate).collect();
+    block_rates.sort_unstable();
+    let mut mempool: Vec<_> = mempool
+        .iter()
+        .map(|(fee, size)| (fee_rate(*fee, *size), *size))
+        .collect();
+    mempool.sort_unstable_by_key(|(fee_rate, _)| std::cmp::Reverse(*fee_rate));
+    TARGETS
+        .iter()
+        .map(|target| {
+            let history_rate = match block_rates.len() {
+                0 => 0,
+                len => block_rates[(len - 1) / *target as usize],
+            };
+            let mempool_rate = mempool_rate(&mempool, space * *target as u64);
+            FeeEstimate {
+                target: *target,
+                fee_rate: history_rate.max(mempool_rate).max(min_fee_rate),
+            }
+        })
+        .collect()
+}
+
+/// `mempool` is sorted by fee rate, highest first.
+fn mempool_rate(mempool: &[(u64, u64)], space: u64) -> u64 {
+    let mut used = 0;
+    for (fee_rate, size) in mempool {
+        used += size;
+        if used > space {
+            return fee_rate + 1;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A full block whose cheapest transaction paid `fee_rate`.
+    fn full_block(fee_rate: u64) -> BlockFees {
+        BlockFees {
+            size: 1000,
+            size_limit: 1000,
+            transactions: vec![(fee_rate * 100, 100), (fee_rate * 300, 100)],
+        }
+    }
+
+    fn fee_rates(estimates: &[FeeEstimate]) -> Vec<u64> {
+        estimates.iter().map(|estimate| estimate.fee_rate).collect()
+    }
+
+    #[test]
+    fn recent_blocks_give_percentile_per_target() {
+        let recent_blocks: Vec<_> = (1..=RECENT_BLOCKS as u64).map(full_block).collect();
+        let estimates = estimate(&recent_blocks, &[], 1000, 0);
+        let targets: Vec<_> = estimates.iter().map(|estimate| estimate.target).collect();
+        assert_eq!(targets, TARGETS);
+        // With 12 blocks, the rates that got into 1 in 1, 1 in 3 and 1 in 6 of them.
+        assert_eq!(fee_rates(&estimates), vec![12, 4, 2]);
+    }
+
+    #[test]
+    fn blocks_with_room_to_spare_ask_for_no_fee() {
+        let mut block = full_block(50);
+        block.size = 500;
+        let estimates = estimate(&[block], &[], 1000, 0);
+        assert_eq!(fee_rates(&estimates), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn empty_mempool_and_no_blocks_give_min_fee_rate() {
+        let estimates = estimate(&[], &[], 1000, 3);
+        assert_eq!(fee_rates(&estimates), vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn mempool_outbids_what_does_not_fit() {
+        // 400 bytes at 10 sats per byte, then 400 bytes at 5 sats per byte.
+        let mempool = [(4000, 400), (2000, 400)];
+        // Only the first 400 bytes fit into one block of 500 bytes.
+        let estimates = estimate(&[], &mempool, 500, 1);
+        assert_eq!(fee_rates(&estimates), vec![6, 1, 1]);
+    }
+}
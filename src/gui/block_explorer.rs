@@ -1,21 +1,43 @@
 use crate::{app::App, thunder::ThunderState};
-use ddk::{
-    bitcoin::{self, constants::COINBASE_MATURITY},
-    types::GetValue,
-};
+
+use super::notifications::Notifications;
+use ddk::{bitcoin, types::GetValue};
 use eframe::egui;
 use human_size::{Byte, Kibibyte, Mebibyte, SpecificSize};
 
 pub struct BlockExplorer {
     height: u32,
+    txid: String,
+    /// Whether the last txid search found nothing.
+    not_found: bool,
 }
 
 impl BlockExplorer {
     pub fn new(height: u32) -> Self {
-        Self { height }
+        Self {
+            height,
+            txid: "".into(),
+            not_found: false,
+        }
+    }
+
+    fn find_transaction(&mut self, app: &App, notifications: &mut Notifications) {
+        let txid: Option<[u8; 32]> = hex::decode(self.txid.trim())
+            .ok()
+            .and_then(|txid| txid.try_into().ok());
+        // A txid that doesn't parse is not found, a failed lookup is an error.
+        let location = txid.and_then(|txid| {
+            notifications
+                .check("find transaction", app.get_transaction(&txid.into()))
+                .flatten()
+        });
+        self.not_found = location.is_none();
+        if let Some((location, _)) = location {
+            self.height = location.height;
+        }
     }
 
-    pub fn show(&mut self, app: &mut App, ui: &mut egui::Ui) {
+    pub fn show(&mut self, app: &mut App, notifications: &mut Notifications, ui: &mut egui::Ui) {
         let max_height = app.node.get_height().unwrap_or(0);
         let header = app.node.get_header(self.height).ok().flatten();
         let body = app.node.get_body(self.height).ok().flatten();
@@ -32,6 +54,17 @@ impl BlockExplorer {
                 if ui.button("latest").clicked() {
                     self.height = max_height;
                 }
+                ui.separator();
+                let txid_edit = egui::TextEdit::singleline(&mut self.txid)
+                    .hint_text("txid")
+                    .desired_width(150.);
+                ui.add(txid_edit);
+                if ui.button("find").clicked() {
+                    self.find_transaction(app, notifications);
+                }
+                if self.not_found {
+                    ui.label("transaction not found");
+                }
             });
             if let (Some(header), Some(body)) = (header, body) {
                 let hash = &format!("{}", header.hash());
@@ -56,13 +89,15 @@ impl BlockExplorer {
                 } else {
                     "".into()
                 };
-                let num_sigops = body.authorizations.len();
+                let num_sigops = ThunderState::body_sigops(&body);
                 ui.monospace(format!("Block hash:       {hash}"));
                 ui.monospace(format!("Merkle root:      {merkle_root}"));
                 ui.monospace(format!("Prev side:        {prev_side_hash}"));
                 ui.monospace(format!("Prev main:        {prev_main_hash}"));
                 ui.monospace(format!("Num transactions: {num_transactions}"));
                 ui.monospace(format!("Coinbase value:   {coinbase_value}"));
+                let mature_at = ThunderState::coinbase_mature_at(self.height);
+                ui.monospace(format!("Coinbase matures: {mature_at}"));
                 ui.monospace(format!("Body size:        {body_size}"));
                 ui.monospace(format!("Num sigops:       {num_sigops}"));
                 ui.separator();
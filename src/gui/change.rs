@@ -0,0 +1,203 @@
+use crate::app::{format_error_chain, App, Error};
+use crate::fee_estimator::FeeEstimate;
+use crate::policy;
+use crate::thunder::Thunder;
+use ddk::bitcoin;
+use ddk::types::{Address, Content, GetValue, Output, Transaction};
+use eframe::egui;
+
+use super::notifications::Notifications;
+use super::task::Task;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FeeKind {
+    Rate,
+    Fixed,
+}
+
+/// Sends what the inputs of the transaction being built don't spend back to the wallet, minus the
+/// fee.
+pub struct Change {
+    automatic: bool,
+    fee_kind: FeeKind,
+    /// Sats per byte for a fee rate, BTC for a fixed fee.
+    fee: String,
+    /// Fresh wallet address, used until the transaction is sent.
+    address: Option<Address>,
+    estimating: Option<Task<Vec<FeeEstimate>>>,
+    estimates: Vec<FeeEstimate>,
+}
+
+impl Default for Change {
+    fn default() -> Self {
+        Self {
+            automatic: true,
+            fee_kind: FeeKind::Rate,
+            fee: "1".into(),
+            address: None,
+            estimating: None,
+            estimates: vec![],
+        }
+    }
+}
+
+impl Change {
+    /// Returns the transaction to send, with the change output if there is one, and its fee.
+    /// `None` if the inputs don't cover the outputs and the fee.
+    pub fn show(
+        &mut self,
+        app: &App,
+        transaction: &Transaction<Thunder>,
+        value_in: u64,
+        notifications: &mut Notifications,
+        ui: &mut egui::Ui,
+    ) -> Option<(Transaction<Thunder>, u64)> {
+        if let Some(estimating) = &mut self.estimating {
+            if let Some(result) = estimating.poll() {
+                self.estimating = None;
+                if let Some(estimates) = notifications.check("estimate fees", result) {
+                    self.estimates = estimates;
+                }
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.automatic, "automatic change");
+            ui.add_enabled_ui(self.automatic, |ui| {
+                ui.selectable_value(&mut self.fee_kind, FeeKind::Rate, "fee rate");
+                ui.selectable_value(&mut self.fee_kind, FeeKind::Fixed, "fixed fee");
+                let fee_edit = egui::TextEdit::singleline(&mut self.fee).desired_width(60.);
+                ui.add(fee_edit);
+                ui.label(match self.fee_kind {
+                    FeeKind::Rate => "sats/byte",
+                    FeeKind::Fixed => "BTC",
+                });
+            });
+        });
+        if self.automatic && self.fee_kind == FeeKind::Rate {
+            self.show_estimates(app, ui);
+        }
+        if self.automatic && self.address.is_none() {
+            let address = app.wallet.get_new_address().map_err(Error::from);
+            self.address = notifications.check("generate change address", address);
+            // Don't retry on every frame.
+            self.automatic = self.address.is_some();
+        }
+        let to_send = match self.add_change(app, transaction, value_in) {
+            Ok(to_send) => to_send,
+            Err(err) => {
+                let message = format!("can't compute the fee: {}", format_error_chain(&err));
+                ui.colored_label(ui.visuals().error_fg_color, message);
+                None
+            }
+        };
+        if let Some((to_send, _)) = &to_send {
+            if let Some(change) = to_send.outputs.get(transaction.outputs.len()) {
+                let change = bitcoin::Amount::from_sat(change.get_value());
+                ui.monospace(format!("change: {change}"));
+            }
+        }
+        to_send
+    }
+
+    /// Fee that `transaction` pays once the change output is added, `None` while the fee field
+    /// doesn't hold a valid fee. Without automatic change the inputs are spent as they are, so
+    /// nothing is set aside for the fee.
+    pub fn get_fee(&self, transaction: &Transaction<Thunder>) -> Result<Option<u64>, Error> {
+        if !self.automatic {
+            return Ok(Some(0));
+        }
+        let Some(address) = self.address else {
+            return Ok(None);
+        };
+        match self.fee_kind {
+            FeeKind::Rate => {
+                let Ok(fee_rate) = self.fee.parse::<u64>() else {
+                    return Ok(None);
+                };
+                let mut with_change = transaction.clone();
+                with_change.outputs.push(Output {
+                    address,
+                    content: Content::Value(0),
+                });
+                let size = policy::estimate_size(&with_change)?;
+                let fee = fee_rate.checked_mul(size).ok_or(Error::ValueOverflow)?;
+                Ok(Some(fee))
+            }
+            FeeKind::Fixed => {
+                let fee = bitcoin::Amount::from_str_in(&self.fee, bitcoin::Denomination::Bitcoin);
+                Ok(fee.ok().map(bitcoin::Amount::to_sat))
+            }
+        }
+    }
+
+    fn add_change(
+        &self,
+        app: &App,
+        transaction: &Transaction<Thunder>,
+        value_in: u64,
+    ) -> Result<Option<(Transaction<Thunder>, u64)>, Error> {
+        let value_out = transaction
+            .outputs
+            .iter()
+            .map(GetValue::get_value)
+            .try_fold(0, u64::checked_add)
+            .ok_or(Error::ValueOverflow)?;
+        let Some(available) = value_in.checked_sub(value_out) else {
+            return Ok(None);
+        };
+        if !self.automatic {
+            return Ok(Some((transaction.clone(), available)));
+        }
+        let (Some(address), Some(fee)) = (self.address, self.get_fee(transaction)?) else {
+            return Ok(None);
+        };
+        let Some(change) = available.checked_sub(fee) else {
+            return Ok(None);
+        };
+        if change == 0 || change < app.policy.dust_threshold {
+            // Not worth a change output, it all goes to the fee.
+            return Ok(Some((transaction.clone(), available)));
+        }
+        let mut with_change = transaction.clone();
+        with_change.outputs.push(Output {
+            address,
+            content: Content::Value(change),
+        });
+        Ok(Some((with_change, fee)))
+    }
+
+    fn show_estimates(&mut self, app: &App, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.estimating.is_none(), egui::Button::new("estimate"))
+                .clicked()
+            {
+                self.estimating = Some(Task::spawn(app, ui.ctx(), |app| async move {
+                    app.run_blocking(|app| app.estimate_fees()).await
+                }));
+            }
+            if self.estimating.is_some() {
+                ui.spinner();
+            }
+            for estimate in &self.estimates {
+                let blocks = if estimate.target == 1 {
+                    "block"
+                } else {
+                    "blocks"
+                };
+                let label = format!(
+                    "{} {blocks}: {} sats/byte",
+                    estimate.target, estimate.fee_rate
+                );
+                if ui.small_button(label).clicked() {
+                    self.fee = format!("{}", estimate.fee_rate);
+                }
+            }
+        });
+    }
+
+    /// Use a fresh change address for the next transaction.
+    pub fn reset(&mut self) {
+        self.address = None;
+    }
+}
@@ -2,9 +2,13 @@ use crate::app::App;
 use ddk::bitcoin;
 use eframe::egui;
 
+use super::notifications::Notifications;
+use super::task::Task;
+
 pub struct Deposit {
     amount: String,
     fee: String,
+    depositing: Option<Task<()>>,
 }
 
 impl Default for Deposit {
@@ -12,12 +16,19 @@ impl Default for Deposit {
         Self {
             amount: "".into(),
             fee: "".into(),
+            depositing: None,
         }
     }
 }
 
 impl Deposit {
-    pub fn show(&mut self, app: &mut App, ui: &mut egui::Ui) {
+    pub fn show(&mut self, app: &mut App, notifications: &mut Notifications, ui: &mut egui::Ui) {
+        if let Some(depositing) = &mut self.depositing {
+            if let Some(result) = depositing.poll() {
+                self.depositing = None;
+                notifications.check("deposit", result);
+            }
+        }
         ui.horizontal(|ui| {
             let amount_edit = egui::TextEdit::singleline(&mut self.amount)
                 .hint_text("amount")
@@ -37,13 +48,20 @@ impl Deposit {
         let fee = bitcoin::Amount::from_str_in(&self.fee, bitcoin::Denomination::Bitcoin);
 
         if ui
-            .add_enabled(amount.is_ok() && fee.is_ok(), egui::Button::new("deposit"))
+            .add_enabled(
+                amount.is_ok() && fee.is_ok() && self.depositing.is_none(),
+                egui::Button::new("deposit"),
+            )
             .clicked()
         {
-            app.deposit(
-                amount.expect("should not happen"),
-                fee.expect("should not happen"),
-            );
+            let amount = amount.expect("should not happen");
+            let fee = fee.expect("should not happen");
+            self.depositing = Some(Task::spawn(app, ui.ctx(), |app| async move {
+                app.deposit(amount, fee).await
+            }));
+        }
+        if self.depositing.is_some() {
+            ui.spinner();
         }
     }
 }
@@ -1,4 +1,5 @@
 use crate::app::App;
+use crate::thunder::ThunderState;
 use ddk::bitcoin;
 use ddk::types::{GetValue, OutPoint};
 use eframe::egui;
@@ -147,6 +148,8 @@ impl MemPoolExplorer {
                     "".into()
                 };
                 ui.monospace(format!("Transaction size: {transaction_size}"));
+                let num_sigops = ThunderState::transaction_sigops(&transaction.transaction);
+                ui.monospace(format!("Num sigops:       {num_sigops}"));
             });
         } else {
             egui::CentralPanel::default().show_inside(ui, |ui| {
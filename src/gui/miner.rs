@@ -1,25 +1,269 @@
+use std::time::{Duration, Instant};
+
 use crate::app::App;
+use crate::auto_miner::{AutoMiner, AutoMinerConfig};
+use crate::block_template::{BlockTemplate, TemplateInfo};
+use crate::bribe::BribeStrategy;
+use ddk::bitcoin;
 use eframe::egui;
 
-pub struct Miner;
+use super::notifications::Notifications;
+use super::task::Task;
+
+/// Transactions reach the mempool over the network too, so the template is rebuilt this often
+/// even if the height stays the same.
+const TEMPLATE_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct Miner {
+    mining: Option<Task<bool>>,
+    auto_miner: Option<AutoMiner>,
+    /// Summary of the last block template, building one reads the whole mempool so it is done in
+    /// the background.
+    template: Option<TemplateInfo>,
+    building_template: Option<Task<TemplateInfo>>,
+    /// Height and time that `template` was built at.
+    template_built_at: Option<(u32, Instant)>,
+    interval: String,
+    mempool_threshold: String,
+}
 
 impl Default for Miner {
     fn default() -> Self {
-        Self
+        Self {
+            mining: None,
+            auto_miner: None,
+            template: None,
+            building_template: None,
+            template_built_at: None,
+            interval: "10".into(),
+            mempool_threshold: "".into(),
+        }
     }
 }
 
 impl Miner {
-    pub fn show(&mut self, app: &mut App, ui: &mut egui::Ui) {
+    pub fn show(&mut self, app: &mut App, notifications: &mut Notifications, ui: &mut egui::Ui) {
+        if let Some(mining) = &mut self.mining {
+            if let Some(result) = mining.poll() {
+                self.mining = None;
+                notifications.check("mine", result);
+                // Mined transactions left the mempool.
+                self.template_built_at = None;
+            }
+        }
         let block_height = app.node.get_height().unwrap_or(0);
+        self.update_template(app, block_height, notifications, ui.ctx());
         let best_hash = app.node.get_best_hash().unwrap_or([0; 32].into());
         ui.label("Block height: ");
         ui.monospace(format!("{block_height}"));
         ui.label("Best hash: ");
         let best_hash = &format!("{best_hash}")[0..8];
         ui.monospace(format!("{best_hash}..."));
-        if ui.button("mine").clicked() {
-            app.mine();
+        if ui
+            .add_enabled(self.mining.is_none(), egui::Button::new("mine"))
+            .clicked()
+        {
+            self.mining = Some(Task::spawn(
+                app,
+                ui.ctx(),
+                |app| async move { app.mine().await },
+            ));
+        }
+        if self.mining.is_some() {
+            ui.spinner();
+        }
+        show_bribe(app, ui);
+        if let Some(template) = &self.template {
+            show_template(template, ui);
+        }
+        ui.separator();
+        self.show_auto_miner(app, notifications, ui);
+    }
+
+    fn update_template(
+        &mut self,
+        app: &App,
+        height: u32,
+        notifications: &mut Notifications,
+        ctx: &egui::Context,
+    ) {
+        if let Some(building_template) = &mut self.building_template {
+            if let Some(result) = building_template.poll() {
+                self.building_template = None;
+                self.template = notifications.check("build block template", result);
+            }
+            return;
+        }
+        let up_to_date = matches!(
+            self.template_built_at,
+            Some((built_height, built_time))
+                if built_height == height && built_time.elapsed() < TEMPLATE_REFRESH_INTERVAL
+        );
+        if !up_to_date {
+            self.template_built_at = Some((height, Instant::now()));
+            self.building_template = Some(Task::spawn(app, ctx, |app| async move {
+                let template = BlockTemplate::build(&app.node, &app.policy)?;
+                Ok(template.info)
+            }));
+        }
+        ctx.request_repaint_after(TEMPLATE_REFRESH_INTERVAL);
+    }
+
+    fn show_auto_miner(&mut self, app: &App, notifications: &mut Notifications, ui: &mut egui::Ui) {
+        if let Some(auto_miner) = &mut self.auto_miner {
+            for err in auto_miner.errors() {
+                notifications.error("auto mine", err);
+            }
+        }
+        let running = self.auto_miner.is_some();
+        ui.label("Auto mine every");
+        let interval_edit = egui::TextEdit::singleline(&mut self.interval)
+            .hint_text("seconds")
+            .desired_width(40.);
+        ui.add_enabled(!running, interval_edit);
+        ui.label("s, or at");
+        let mempool_threshold_edit = egui::TextEdit::singleline(&mut self.mempool_threshold)
+            .hint_text("any")
+            .desired_width(40.);
+        ui.add_enabled(!running, mempool_threshold_edit);
+        ui.label("txs");
+        let interval: Option<u64> = self.interval.parse().ok();
+        let mempool_threshold: Option<Option<usize>> = if self.mempool_threshold.is_empty() {
+            Some(None)
+        } else {
+            self.mempool_threshold.parse().ok().map(Some)
+        };
+        if let Some(auto_miner) = self.auto_miner.take() {
+            if ui.button("stop").clicked() {
+                auto_miner.stop();
+            } else {
+                self.auto_miner = Some(auto_miner);
+            }
+            ui.spinner();
+        } else if ui
+            .add_enabled(
+                interval.is_some() && mempool_threshold.is_some(),
+                egui::Button::new("start"),
+            )
+            .clicked()
+        {
+            let config = AutoMinerConfig {
+                interval: Duration::from_secs(interval.expect("should not happen")),
+                mempool_threshold: mempool_threshold.expect("should not happen"),
+            };
+            self.auto_miner = Some(AutoMiner::start(app, config));
+        }
+        let mining_stats = app.mining_stats.lock().unwrap().clone();
+        let response = ui.monospace(format!(
+            "BMM {}/{} ok",
+            mining_stats.successes, mining_stats.attempts
+        ));
+        if let Some(last_error) = mining_stats.last_error {
+            response.on_hover_text(format!(
+                "{} failed\nlast error: {last_error}",
+                mining_stats.failures
+            ));
         }
     }
 }
+
+fn show_template(info: &TemplateInfo, ui: &mut egui::Ui) {
+    let fees = bitcoin::Amount::from_sat(info.fees);
+    ui.monospace(format!("{} txs, {fees}", info.num_transactions))
+        .on_hover_text(format!(
+            "size: {}/{} bytes\nsigops: {}/{}",
+            info.size, info.size_limit, info.sigops, info.sigops_limit
+        ));
+}
+
+fn show_bribe(app: &App, ui: &mut egui::Ui) {
+    const STRATEGIES: [(&str, BribeStrategy); 4] = [
+        ("fixed", BribeStrategy::Fixed { amount: 1000 }),
+        (
+            "fee percentage",
+            BribeStrategy::FeePercentage { percent: 100 },
+        ),
+        (
+            "fee percentage with floor",
+            BribeStrategy::FeePercentageWithFloor {
+                percent: 100,
+                floor: 1000,
+            },
+        ),
+        (
+            "fee percentage or empty block",
+            BribeStrategy::FeePercentageOrEmptyBlock {
+                percent: 100,
+                empty_block: 1000,
+            },
+        ),
+    ];
+    ui.menu_button("bribe", |ui| {
+        let mut bribe = app.bribe.lock().unwrap();
+        for (label, strategy) in STRATEGIES {
+            let selected =
+                std::mem::discriminant(&bribe.strategy) == std::mem::discriminant(&strategy);
+            if ui.selectable_label(selected, label).clicked() && !selected {
+                bribe.strategy = strategy;
+            }
+        }
+        ui.separator();
+        egui::Grid::new("bribe").show(ui, |ui| {
+            match &mut bribe.strategy {
+                BribeStrategy::Fixed { amount } => {
+                    ui.label("amount");
+                    ui.add(egui::DragValue::new(amount).suffix(" sats"));
+                    ui.end_row();
+                }
+                BribeStrategy::FeePercentage { percent } => {
+                    ui.label("share of fees");
+                    ui.add(
+                        egui::DragValue::new(percent)
+                            .clamp_range(0..=100)
+                            .suffix("%"),
+                    );
+                    ui.end_row();
+                }
+                BribeStrategy::FeePercentageWithFloor { percent, floor } => {
+                    ui.label("share of fees");
+                    ui.add(
+                        egui::DragValue::new(percent)
+                            .clamp_range(0..=100)
+                            .suffix("%"),
+                    );
+                    ui.end_row();
+                    ui.label("floor");
+                    ui.add(egui::DragValue::new(floor).suffix(" sats"));
+                    ui.end_row();
+                }
+                BribeStrategy::FeePercentageOrEmptyBlock {
+                    percent,
+                    empty_block,
+                } => {
+                    ui.label("share of fees");
+                    ui.add(
+                        egui::DragValue::new(percent)
+                            .clamp_range(0..=100)
+                            .suffix("%"),
+                    );
+                    ui.end_row();
+                    ui.label("empty block");
+                    ui.add(egui::DragValue::new(empty_block).suffix(" sats"));
+                    ui.end_row();
+                }
+            }
+            let mut capped = bribe.max.is_some();
+            ui.checkbox(&mut capped, "cap");
+            let mut max = bribe.max.unwrap_or(10_000);
+            ui.add_enabled(capped, egui::DragValue::new(&mut max).suffix(" sats"));
+            bribe.max = capped.then_some(max);
+            ui.end_row();
+            ui.label("raise after failure");
+            ui.add(egui::DragValue::new(&mut bribe.escalation_percent).suffix("%"));
+            ui.end_row();
+            ui.label("main fee");
+            ui.add(egui::DragValue::new(&mut bribe.main_fee).suffix(" sats"));
+            ui.end_row();
+        });
+    });
+}
@@ -1,29 +1,46 @@
 use std::collections::HashSet;
 
 use crate::app::App;
+use crate::thunder::Thunder;
 use ddk::bitcoin;
-use ddk::types::GetValue;
+use ddk::types::{GetValue, Transaction, Txid};
 use eframe::egui;
 
 mod block_explorer;
+mod change;
 mod deposit;
 mod mempool_explorer;
 mod miner;
+mod notifications;
+mod receive;
 mod seed;
+mod send;
+mod task;
 mod utxo_creator;
 mod utxo_selector;
 
 use block_explorer::BlockExplorer;
+use change::Change;
 use deposit::Deposit;
 use mempool_explorer::MemPoolExplorer;
 use miner::Miner;
+use notifications::Notifications;
+use receive::Receive;
 use seed::SetSeed;
+use send::SendTab;
+use task::Task;
 use utxo_selector::{show_utxo, UtxoSelector};
 
 use self::utxo_creator::UtxoCreator;
 
 pub struct EguiApp {
     app: App,
+    transaction: Transaction<Thunder>,
+    sending: Option<Task<Txid>>,
+    change: Change,
+    send: SendTab,
+    receive: Receive,
+    notifications: Notifications,
     set_seed: SetSeed,
     miner: Miner,
     deposit: Deposit,
@@ -36,6 +53,8 @@ pub struct EguiApp {
 
 #[derive(Eq, PartialEq)]
 enum Tab {
+    Send,
+    Receive,
     TransactionBuilder,
     MemPoolExplorer,
     BlockExplorer,
@@ -50,6 +69,15 @@ impl EguiApp {
         let height = app.node.get_height().unwrap_or(0);
         Self {
             app,
+            transaction: Transaction {
+                inputs: vec![],
+                outputs: vec![],
+            },
+            sending: None,
+            change: Change::default(),
+            send: SendTab::default(),
+            receive: Receive::default(),
+            notifications: Notifications::default(),
             set_seed: SetSeed::default(),
             miner: Miner::default(),
             deposit: Deposit::default(),
@@ -57,45 +85,65 @@ impl EguiApp {
             utxo_creator: UtxoCreator::default(),
             mempool_explorer: MemPoolExplorer::default(),
             block_explorer: BlockExplorer::new(height),
-            tab: Tab::TransactionBuilder,
+            tab: Tab::Send,
         }
     }
 }
 
 impl eframe::App for EguiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(sending) = &mut self.sending {
+            if let Some(result) = sending.poll() {
+                self.sending = None;
+                if self.notifications.check("sign and send", result).is_some() {
+                    self.transaction = Transaction {
+                        inputs: vec![],
+                        outputs: vec![],
+                    };
+                    self.change.reset();
+                }
+            }
+        }
         if self.app.wallet.has_seed().unwrap_or(false) {
             egui::TopBottomPanel::top("tabs").show(ctx, |ui| {
                 ui.horizontal(|ui| {
-                    ui.selectable_value(
-                        &mut self.tab,
-                        Tab::TransactionBuilder,
-                        "transaction builder",
-                    );
+                    ui.selectable_value(&mut self.tab, Tab::Send, "send");
+                    ui.selectable_value(&mut self.tab, Tab::Receive, "receive");
+                    ui.selectable_value(&mut self.tab, Tab::TransactionBuilder, "advanced");
                     ui.selectable_value(&mut self.tab, Tab::MemPoolExplorer, "mempool explorer");
                     ui.selectable_value(&mut self.tab, Tab::BlockExplorer, "block explorer");
+                    ui.separator();
+                    self.notifications.show_log_toggle(ui);
                 });
             });
             egui::TopBottomPanel::bottom("util").show(ctx, |ui| {
                 ui.horizontal(|ui| {
-                    self.miner.show(&mut self.app, ui);
+                    self.miner.show(&mut self.app, &mut self.notifications, ui);
                     ui.separator();
-                    self.deposit.show(&mut self.app, ui);
+                    self.deposit
+                        .show(&mut self.app, &mut self.notifications, ui);
                 });
             });
             egui::CentralPanel::default().show(ctx, |ui| match self.tab {
+                Tab::Send => {
+                    self.send.show(&mut self.app, &mut self.notifications, ui);
+                }
+                Tab::Receive => {
+                    self.receive
+                        .show(&mut self.app, &mut self.notifications, ui);
+                }
                 Tab::TransactionBuilder => {
-                    let selected: HashSet<_> =
-                        self.app.transaction.inputs.iter().cloned().collect();
+                    let selected: HashSet<_> = self.transaction.inputs.iter().cloned().collect();
                     let value_in: u64 = self
                         .app
                         .utxos
+                        .read()
+                        .unwrap()
                         .iter()
                         .filter(|(outpoint, _)| selected.contains(outpoint))
                         .map(|(_, output)| output.get_value())
                         .sum();
                     let value_out: u64 = self
-                        .app
                         .transaction
                         .outputs
                         .iter()
@@ -105,20 +153,20 @@ impl eframe::App for EguiApp {
                         .exact_width(250.)
                         .resizable(false)
                         .show_inside(ui, |ui| {
-                            self.utxo_selector.show(&mut self.app, ui);
+                            self.utxo_selector.show(
+                                &mut self.app,
+                                &mut self.transaction,
+                                &self.change,
+                                &mut self.notifications,
+                                ui,
+                            );
                         });
                     egui::SidePanel::left("value_in")
                         .exact_width(250.)
                         .resizable(false)
                         .show_inside(ui, |ui| {
                             ui.heading("Value In");
-                            let mut utxos: Vec<_> = self
-                                .app
-                                .utxos
-                                .iter()
-                                .filter(|(outpoint, _)| selected.contains(outpoint))
-                                .collect();
-                            utxos.sort_by_key(|(outpoint, _)| format!("{outpoint}"));
+                            let utxos = self.app.utxos.read().unwrap();
                             ui.separator();
                             ui.monospace(format!("Total: {}", bitcoin::Amount::from_sat(value_in)));
                             ui.separator();
@@ -128,18 +176,23 @@ impl eframe::App for EguiApp {
                                 ui.monospace("value");
                                 ui.end_row();
                                 let mut remove = None;
-                                for (vout, outpoint) in
-                                    self.app.transaction.inputs.iter().enumerate()
-                                {
-                                    let output = &self.app.utxos[&outpoint];
-                                    show_utxo(ui, &outpoint, output);
+                                for (vout, outpoint) in self.transaction.inputs.iter().enumerate() {
+                                    // The input can be spent elsewhere while it is selected here.
+                                    match utxos.get(outpoint) {
+                                        Some(output) => show_utxo(ui, outpoint, output),
+                                        None => {
+                                            ui.weak("spent");
+                                            ui.weak(format!("{outpoint}"));
+                                            ui.label("");
+                                        }
+                                    }
                                     if ui.button("remove").clicked() {
                                         remove = Some(vout);
                                     }
                                     ui.end_row();
                                 }
                                 if let Some(vout) = remove {
-                                    self.app.transaction.inputs.remove(vout);
+                                    self.transaction.inputs.remove(vout);
                                 }
                             });
                         });
@@ -160,9 +213,7 @@ impl eframe::App for EguiApp {
                                 ui.monospace("address");
                                 ui.monospace("value");
                                 ui.end_row();
-                                for (vout, output) in
-                                    self.app.transaction.outputs.iter().enumerate()
-                                {
+                                for (vout, output) in self.transaction.outputs.iter().enumerate() {
                                     let address = &format!("{}", output.address)[0..8];
                                     let value = bitcoin::Amount::from_sat(output.get_value());
                                     ui.monospace(format!("{vout}"));
@@ -179,7 +230,7 @@ impl eframe::App for EguiApp {
                                     ui.end_row();
                                 }
                                 if let Some(vout) = remove {
-                                    self.app.transaction.outputs.remove(vout);
+                                    self.transaction.outputs.remove(vout);
                                 }
                             });
                         });
@@ -188,20 +239,52 @@ impl eframe::App for EguiApp {
                         .resizable(false)
                         .show_separator_line(false)
                         .show_inside(ui, |ui| {
-                            self.utxo_creator.show(&mut self.app, ui);
+                            self.utxo_creator.show(
+                                &mut self.app,
+                                &mut self.transaction,
+                                &mut self.notifications,
+                                ui,
+                            );
                             ui.separator();
                             ui.heading("Transaction");
-                            let txid = &format!("{}", self.app.transaction.txid())[0..8];
-                            ui.monospace(format!("txid: {txid}"));
-                            if value_in >= value_out {
-                                let fee = value_in - value_out;
-                                let fee = bitcoin::Amount::from_sat(fee);
-                                ui.monospace(format!("fee:  {fee}"));
-                                if ui.button("sign and send").clicked() {
-                                    self.app.sign_and_send().unwrap_or(());
+                            let to_send = self.change.show(
+                                &self.app,
+                                &self.transaction,
+                                value_in,
+                                &mut self.notifications,
+                                ui,
+                            );
+                            if let Some((transaction, fee)) = to_send {
+                                let txid = &format!("{}", transaction.txid())[0..8];
+                                ui.monospace(format!("txid: {txid}"));
+                                ui.monospace(format!("fee:  {}", bitcoin::Amount::from_sat(fee)));
+                                let fee_too_high = fee > self.app.max_fee;
+                                if fee_too_high {
+                                    let max_fee = bitcoin::Amount::from_sat(self.app.max_fee);
+                                    ui.colored_label(
+                                        ui.visuals().error_fg_color,
+                                        format!("fee is above the maximum of {max_fee}"),
+                                    );
                                 }
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add_enabled(
+                                            self.sending.is_none() && !fee_too_high,
+                                            egui::Button::new("sign and send"),
+                                        )
+                                        .clicked()
+                                    {
+                                        self.sending =
+                                            Some(Task::spawn(&self.app, ctx, |app| async move {
+                                                app.sign_and_send(transaction).await
+                                            }));
+                                    }
+                                    if self.sending.is_some() {
+                                        ui.spinner();
+                                    }
+                                });
                             } else {
-                                ui.label("Not Enough Value In");
+                                ui.label("Not enough value in for the outputs and fee");
                             }
                         });
                 }
@@ -209,15 +292,19 @@ impl eframe::App for EguiApp {
                     self.mempool_explorer.show(&mut self.app, ui);
                 }
                 Tab::BlockExplorer => {
-                    self.block_explorer.show(&mut self.app, ui);
+                    self.block_explorer
+                        .show(&mut self.app, &mut self.notifications, ui);
                 }
             });
         } else {
             egui::CentralPanel::default().show(ctx, |_ui| {
                 egui::Window::new("Set Seed").show(ctx, |ui| {
-                    self.set_seed.show(&mut self.app, ui);
+                    self.set_seed
+                        .show(&mut self.app, &mut self.notifications, ui);
                 });
             });
         }
+        self.notifications.show_toasts(ctx);
+        self.notifications.show_log(ctx);
     }
 }
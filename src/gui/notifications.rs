@@ -0,0 +1,106 @@
+use std::time::{Duration, Instant};
+
+use crate::app::{format_error_chain, Error};
+use eframe::egui;
+
+/// How long an error stays on screen as a toast, it is kept in the error log after that.
+const TOAST_DURATION: Duration = Duration::from_secs(8);
+
+struct Notification {
+    action: &'static str,
+    message: String,
+    time: Instant,
+    dismissed: bool,
+}
+
+#[derive(Default)]
+pub struct Notifications {
+    log: Vec<Notification>,
+    show_log: bool,
+}
+
+impl Notifications {
+    /// Record a failed action, the message includes the whole error source chain.
+    pub fn error(&mut self, action: &'static str, err: Error) {
+        self.log.push(Notification {
+            action,
+            message: format_error_chain(&err),
+            time: Instant::now(),
+            dismissed: false,
+        });
+    }
+
+    /// Record the error of a failed action, if there is one.
+    pub fn check<T>(&mut self, action: &'static str, result: Result<T, Error>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.error(action, err);
+                None
+            }
+        }
+    }
+
+    pub fn show_toasts(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        let mut recent = self
+            .log
+            .iter_mut()
+            .filter(|notification| {
+                !notification.dismissed && now.duration_since(notification.time) < TOAST_DURATION
+            })
+            .peekable();
+        if recent.peek().is_none() {
+            return;
+        }
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-10., -50.])
+            .show(ctx, |ui| {
+                for notification in recent {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(400.);
+                        ui.horizontal(|ui| {
+                            let error_color = ui.visuals().error_fg_color;
+                            ui.colored_label(
+                                error_color,
+                                format!("{} failed", notification.action),
+                            );
+                            if ui.small_button("x").clicked() {
+                                notification.dismissed = true;
+                            }
+                        });
+                        ui.label(&notification.message);
+                    });
+                }
+            });
+        // Repaint again so that toasts disappear once they expire.
+        ctx.request_repaint_after(Duration::from_secs(1));
+    }
+
+    pub fn show_log_toggle(&mut self, ui: &mut egui::Ui) {
+        let text = format!("errors ({})", self.log.len());
+        ui.toggle_value(&mut self.show_log, text);
+    }
+
+    pub fn show_log(&mut self, ctx: &egui::Context) {
+        let log = &self.log;
+        egui::Window::new("Error log")
+            .open(&mut self.show_log)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                if log.is_empty() {
+                    ui.label("No errors");
+                }
+                for notification in log.iter().rev() {
+                    let elapsed = notification.time.elapsed().as_secs();
+                    let error_color = ui.visuals().error_fg_color;
+                    ui.colored_label(
+                        error_color,
+                        format!("{} failed {elapsed}s ago", notification.action),
+                    );
+                    ui.monospace(&notification.message);
+                    ui.separator();
+                }
+            });
+    }
+}
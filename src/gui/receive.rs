@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use crate::app::{App, Error};
+use ddk::bitcoin;
+use ddk::types::Address;
+use eframe::egui;
+use qrcode::QrCode;
+
+use super::notifications::Notifications;
+use super::task::Task;
+
+/// Size of one QR code module in points.
+const QR_MODULE_SIZE: f32 = 4.;
+/// Blank modules around the QR code, scanners need them to find it.
+const QR_QUIET_ZONE: usize = 4;
+
+/// Wallet addresses with their labels and what they received, and QR codes to share them.
+#[derive(Default)]
+pub struct Receive {
+    /// Every wallet address with the total value it received, sorted by address.
+    addresses: Vec<(Address, u64)>,
+    /// Reading the totals of many addresses from the index runs in the background.
+    loading: Option<Task<Vec<(Address, u64)>>>,
+    /// Index height and number of wallet addresses that `addresses` is up to date with.
+    loaded_at: Option<(Option<u32>, u64)>,
+    /// Whether reading the index height or number of addresses failed, so that the error is
+    /// reported once rather than every frame.
+    failed: bool,
+    /// Labels as they are being edited, they are saved when the field loses focus.
+    labels: HashMap<Address, String>,
+    /// Address whose QR code is shown.
+    qr_code: Option<Address>,
+}
+
+impl Receive {
+    pub fn show(&mut self, app: &mut App, notifications: &mut Notifications, ui: &mut egui::Ui) {
+        if let Some(loading) = &mut self.loading {
+            if let Some(result) = loading.poll() {
+                self.loading = None;
+                if let Some(addresses) = notifications.check("load addresses", result) {
+                    self.addresses = addresses;
+                }
+            }
+        }
+        // Totals only change when the index connects blocks, which can lag behind the node.
+        let current = app.get_index_height().and_then(|height| {
+            let num_addresses = app.wallet.get_num_addresses()? as u64;
+            Ok((height, num_addresses))
+        });
+        match current {
+            Ok(current) => {
+                self.failed = false;
+                if self.loading.is_none() && self.loaded_at != Some(current) {
+                    self.loaded_at = Some(current);
+                    self.loading = Some(Task::spawn(app, ui.ctx(), |app| async move {
+                        app.run_blocking(|app| load_addresses(&app)).await
+                    }));
+                }
+            }
+            Err(err) => {
+                if !self.failed {
+                    self.failed = true;
+                    notifications.error("load addresses", err);
+                }
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.heading("Receive");
+            if ui.button("new address").clicked() {
+                let address = app.wallet.get_new_address().map_err(Error::from);
+                if let Some(address) = notifications.check("generate address", address) {
+                    self.qr_code = Some(address);
+                }
+            }
+            if self.loading.is_some() {
+                ui.spinner();
+            }
+        });
+        ui.separator();
+        let mut num_utxos: HashMap<Address, usize> = HashMap::new();
+        for output in app.utxos.read().unwrap().values() {
+            *num_utxos.entry(output.address).or_default() += 1;
+        }
+        egui::Grid::new("addresses").striped(true).show(ui, |ui| {
+            ui.monospace("label");
+            ui.monospace("address");
+            ui.monospace("received").on_hover_text(
+                "Everything paid to the address in blocks, and deposits from when the wallet saw \
+                 them",
+            );
+            ui.monospace("utxos");
+            ui.end_row();
+            for (address, received) in &self.addresses {
+                let label = self.labels.entry(*address).or_insert_with(|| {
+                    let labels = app.labels.lock().unwrap();
+                    labels.get(address).unwrap_or_default().into()
+                });
+                let response = ui.add(egui::TextEdit::singleline(label).desired_width(120.));
+                if response.lost_focus() {
+                    let mut labels = app.labels.lock().unwrap();
+                    if labels.get(address).unwrap_or_default() != label.trim() {
+                        let result = labels.set(address, label).map_err(Error::from);
+                        notifications.check("save label", result);
+                    }
+                }
+                ui.monospace(format!("{address}"));
+                let received = bitcoin::Amount::from_sat(*received);
+                ui.monospace(format!("{received}"));
+                let num_utxos = num_utxos.get(address).copied().unwrap_or(0);
+                ui.monospace(format!("{num_utxos}"));
+                if ui.button("copy").clicked() {
+                    ui.output_mut(|output| output.copied_text = format!("{address}"));
+                }
+                ui.selectable_value(&mut self.qr_code, Some(*address), "QR");
+                ui.end_row();
+            }
+        });
+        if let Some(address) = self.qr_code {
+            ui.separator();
+            ui.monospace(format!("{address}"));
+            show_qr_code(&format!("{address}"), ui);
+        }
+    }
+}
+
+fn load_addresses(app: &App) -> Result<Vec<(Address, u64)>, Error> {
+    let mut addresses: Vec<_> = app.wallet.get_addresses()?.into_iter().collect();
+    addresses.sort_by_key(|address| format!("{address}"));
+    addresses
+        .into_iter()
+        .map(|address| Ok((address, app.get_received_value(&address)?)))
+        .collect()
+}
+
+/// Paint `data` as a QR code, dark modules on a white background so that it scans with any theme.
+fn show_qr_code(data: &str, ui: &mut egui::Ui) {
+    let code = match QrCode::new(data.as_bytes()) {
+        Ok(code) => code,
+        Err(err) => {
+            ui.label(format!("can't create QR code: {err}"));
+            return;
+        }
+    };
+    let width = code.width();
+    let size = (width + 2 * QR_QUIET_ZONE) as f32 * QR_MODULE_SIZE;
+    let (response, painter) = ui.allocate_painter(egui::vec2(size, size), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0., egui::Color32::WHITE);
+    for (index, color) in code.to_colors().into_iter().enumerate() {
+        if color != qrcode::Color::Dark {
+            continue;
+        }
+        let x = (index % width + QR_QUIET_ZONE) as f32 * QR_MODULE_SIZE;
+        let y = (index / width + QR_QUIET_ZONE) as f32 * QR_MODULE_SIZE;
+        let module = egui::Rect::from_min_size(
+            rect.min + egui::vec2(x, y),
+            egui::vec2(QR_MODULE_SIZE, QR_MODULE_SIZE),
+        );
+        painter.rect_filled(module, 0., egui::Color32::BLACK);
+    }
+}
@@ -1,6 +1,8 @@
-use crate::app::App;
+use crate::app::{App, Error};
 use eframe::egui;
 
+use super::notifications::Notifications;
+
 pub struct SetSeed {
     seed: String,
     passphrase: String,
@@ -16,7 +18,7 @@ impl Default for SetSeed {
 }
 
 impl SetSeed {
-    pub fn show(&mut self, app: &App, ui: &mut egui::Ui) {
+    pub fn show(&mut self, app: &App, notifications: &mut Notifications, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             let seed_edit = egui::TextEdit::singleline(&mut self.seed)
                 .hint_text("seed")
@@ -40,9 +42,11 @@ impl SetSeed {
         {
             let mnemonic = mnemonic.expect("should never happen");
             let seed = bip39::Seed::new(&mnemonic, &self.passphrase);
-            app.wallet
+            let result = app
+                .wallet
                 .set_seed(seed.as_bytes().try_into().expect("seed it not 64 bytes"))
-                .expect("failed to set HD wallet seed");
+                .map_err(Error::from);
+            notifications.check("set seed", result);
         }
     }
 }
@@ -0,0 +1,195 @@
+use crate::app::App;
+use crate::thunder::Thunder;
+use ddk::bitcoin;
+use ddk::types::{Address, Content, GetValue, Output, Transaction, Txid};
+use eframe::egui;
+
+use super::notifications::Notifications;
+use super::task::Task;
+
+struct Recipient {
+    address: String,
+    amount: String,
+}
+
+impl Default for Recipient {
+    fn default() -> Self {
+        Self {
+            address: "".into(),
+            amount: "".into(),
+        }
+    }
+}
+
+/// Transaction built by the wallet for the payments in the form, shown before it is signed.
+struct Review {
+    payments: Vec<(Address, u64)>,
+    fee_rate: u64,
+    transaction: Transaction<Thunder>,
+    fee: u64,
+}
+
+/// Pay one or more addresses, the wallet picks the inputs and adds change.
+pub struct SendTab {
+    recipients: Vec<Recipient>,
+    /// Sats per byte.
+    fee_rate: String,
+    review: Option<Review>,
+    sending: Option<Task<Txid>>,
+}
+
+impl Default for SendTab {
+    fn default() -> Self {
+        Self {
+            recipients: vec![Recipient::default()],
+            fee_rate: "1".into(),
+            review: None,
+            sending: None,
+        }
+    }
+}
+
+impl SendTab {
+    pub fn show(&mut self, app: &mut App, notifications: &mut Notifications, ui: &mut egui::Ui) {
+        if let Some(sending) = &mut self.sending {
+            if let Some(result) = sending.poll() {
+                self.sending = None;
+                if notifications.check("sign and send", result).is_some() {
+                    *self = Self::default();
+                }
+            }
+        }
+        ui.heading("Send");
+        ui.separator();
+        self.show_recipients(ui);
+        ui.horizontal(|ui| {
+            ui.monospace("Fee rate: ");
+            ui.add(egui::TextEdit::singleline(&mut self.fee_rate).desired_width(60.));
+            ui.label("sats/byte");
+        });
+        let payments = self.parse_payments();
+        let fee_rate: Option<u64> = self.fee_rate.parse().ok();
+        // Anything that was reviewed has to be reviewed again after the form changed.
+        if let Some(review) = &self.review {
+            if payments.as_ref() != Some(&review.payments) || fee_rate != Some(review.fee_rate) {
+                self.review = None;
+            }
+        }
+        if ui
+            .add_enabled(
+                payments.is_some() && fee_rate.is_some() && self.sending.is_none(),
+                egui::Button::new("review"),
+            )
+            .clicked()
+        {
+            let payments = payments.expect("should not happen");
+            let fee_rate = fee_rate.expect("should not happen");
+            let outputs = payments
+                .iter()
+                .map(|(address, value)| Output {
+                    address: *address,
+                    content: Content::Value(*value),
+                })
+                .collect();
+            let transaction = app.create_transaction_with_fee_rate(outputs, fee_rate);
+            if let Some((transaction, fee)) = notifications.check("review", transaction) {
+                self.review = Some(Review {
+                    payments,
+                    fee_rate,
+                    transaction,
+                    fee,
+                });
+            }
+        }
+        ui.separator();
+        self.show_review(app, ui);
+    }
+
+    fn show_recipients(&mut self, ui: &mut egui::Ui) {
+        let mut remove = None;
+        egui::Grid::new("recipients").show(ui, |ui| {
+            let removable = self.recipients.len() > 1;
+            for (index, recipient) in self.recipients.iter_mut().enumerate() {
+                let address_edit = egui::TextEdit::singleline(&mut recipient.address)
+                    .hint_text("address")
+                    .desired_width(300.);
+                ui.add(address_edit);
+                let amount_edit = egui::TextEdit::singleline(&mut recipient.amount)
+                    .hint_text("amount")
+                    .desired_width(100.);
+                ui.add(amount_edit);
+                ui.label("BTC");
+                if ui
+                    .add_enabled(removable, egui::Button::new("remove"))
+                    .clicked()
+                {
+                    remove = Some(index);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(index) = remove {
+            self.recipients.remove(index);
+        }
+        if ui.button("add recipient").clicked() {
+            self.recipients.push(Recipient::default());
+        }
+    }
+
+    /// Addresses and amounts in sats, `None` if any of them is invalid.
+    fn parse_payments(&self) -> Option<Vec<(Address, u64)>> {
+        self.recipients
+            .iter()
+            .map(|recipient| {
+                let address: Address = recipient.address.parse().ok()?;
+                let amount =
+                    bitcoin::Amount::from_str_in(&recipient.amount, bitcoin::Denomination::Bitcoin)
+                        .ok()
+                        .filter(|amount| *amount > bitcoin::Amount::ZERO)?;
+                Some((address, amount.to_sat()))
+            })
+            .collect()
+    }
+
+    fn show_review(&mut self, app: &App, ui: &mut egui::Ui) {
+        let Some(review) = &self.review else {
+            return;
+        };
+        let transaction = &review.transaction;
+        let paid: u64 = review.payments.iter().map(|(_, value)| value).sum();
+        let value_out: u64 = transaction.outputs.iter().map(GetValue::get_value).sum();
+        let change = value_out - paid;
+        ui.monospace(format!("Inputs:  {}", transaction.inputs.len()));
+        ui.monospace(format!("Paying:  {}", bitcoin::Amount::from_sat(paid)));
+        ui.monospace(format!("Change:  {}", bitcoin::Amount::from_sat(change)));
+        ui.monospace(format!(
+            "Fee:     {}",
+            bitcoin::Amount::from_sat(review.fee)
+        ));
+        let fee_too_high = review.fee > app.max_fee;
+        if fee_too_high {
+            let max_fee = bitcoin::Amount::from_sat(app.max_fee);
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                format!("fee is above the maximum of {max_fee}"),
+            );
+        }
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    self.sending.is_none() && !fee_too_high,
+                    egui::Button::new("sign and send"),
+                )
+                .clicked()
+            {
+                let transaction = transaction.clone();
+                self.sending = Some(Task::spawn(app, ui.ctx(), |app| async move {
+                    app.sign_and_send(transaction).await
+                }));
+            }
+            if self.sending.is_some() {
+                ui.spinner();
+            }
+        });
+    }
+}
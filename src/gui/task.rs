@@ -0,0 +1,38 @@
+use std::future::Future;
+
+use crate::app::{App, Error};
+use eframe::egui;
+use tokio::sync::oneshot;
+
+/// App operation running in the background on the tokio runtime, so that the GUI keeps repainting
+/// while it waits for the mainchain.
+pub struct Task<T> {
+    receiver: oneshot::Receiver<Result<T, Error>>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// `task` gets its own handle to the app, the future it returns outlives the current frame.
+    pub fn spawn<F>(app: &App, ctx: &egui::Context, task: impl FnOnce(App) -> F) -> Self
+    where
+        F: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        let future = task(app.clone());
+        let (sender, receiver) = oneshot::channel();
+        let ctx = ctx.clone();
+        app.spawn(async move {
+            // The receiver is gone if the widget that started the task was dropped.
+            let _ = sender.send(future.await);
+            ctx.request_repaint();
+        });
+        Self { receiver }
+    }
+
+    /// Returns the result once the task is done, and `None` while it is still running.
+    pub fn poll(&mut self) -> Option<Result<T, Error>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(oneshot::error::TryRecvError::Empty) => None,
+            Err(oneshot::error::TryRecvError::Closed) => Some(Err(Error::TaskCancelled)),
+        }
+    }
+}
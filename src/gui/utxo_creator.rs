@@ -1,21 +1,30 @@
-use crate::app::App;
-use crate::thunder::Thunder;
+use crate::app::{App, Error};
+use crate::thunder::{Thunder, ThunderState};
 use ddk::bitcoin;
-use ddk::types::{Content, Output};
+use ddk::types::{Content, Output, Transaction};
 use eframe::egui;
 
+use super::notifications::Notifications;
+use super::task::Task;
+
 pub struct UtxoCreator {
     utxo_type: UtxoType,
     value: String,
     address: String,
     main_address: String,
     main_fee: String,
+    /// Hex encoded contents of a data output.
+    data: String,
+    locked_until: String,
+    generating_main_address: Option<Task<bitcoin::Address<bitcoin::address::NetworkChecked>>>,
 }
 
 #[derive(Eq, PartialEq)]
 enum UtxoType {
     Regular,
     Withdrawal,
+    Data,
+    Locked,
 }
 
 impl std::fmt::Display for UtxoType {
@@ -23,6 +32,8 @@ impl std::fmt::Display for UtxoType {
         match self {
             Self::Regular => write!(f, "regular"),
             Self::Withdrawal => write!(f, "withdrawal"),
+            Self::Data => write!(f, "data"),
+            Self::Locked => write!(f, "locked"),
         }
     }
 }
@@ -34,13 +45,30 @@ impl Default for UtxoCreator {
             address: "".into(),
             main_address: "".into(),
             main_fee: "".into(),
+            data: "".into(),
+            locked_until: "".into(),
+            generating_main_address: None,
             utxo_type: UtxoType::Regular,
         }
     }
 }
 
 impl UtxoCreator {
-    pub fn show(&mut self, app: &mut App, ui: &mut egui::Ui) {
+    pub fn show(
+        &mut self,
+        app: &mut App,
+        transaction: &mut Transaction<Thunder>,
+        notifications: &mut Notifications,
+        ui: &mut egui::Ui,
+    ) {
+        if let Some(generating_main_address) = &mut self.generating_main_address {
+            if let Some(result) = generating_main_address.poll() {
+                self.generating_main_address = None;
+                if let Some(main_address) = notifications.check("generate main address", result) {
+                    self.main_address = format!("{main_address}");
+                }
+            }
+        }
         ui.horizontal(|ui| {
             ui.heading("Create");
             egui::ComboBox::from_id_source("utxo_type")
@@ -48,35 +76,67 @@ impl UtxoCreator {
                 .show_ui(ui, |ui| {
                     ui.selectable_value(&mut self.utxo_type, UtxoType::Regular, "regular");
                     ui.selectable_value(&mut self.utxo_type, UtxoType::Withdrawal, "withdrawal");
+                    ui.selectable_value(&mut self.utxo_type, UtxoType::Data, "data");
+                    ui.selectable_value(&mut self.utxo_type, UtxoType::Locked, "locked");
                 });
             ui.heading("UTXO");
         });
         ui.separator();
-        ui.horizontal(|ui| {
-            ui.monospace("Value:       ");
-            ui.add(egui::TextEdit::singleline(&mut self.value));
-            ui.monospace("BTC");
-        });
+        if self.utxo_type == UtxoType::Data {
+            ui.horizontal(|ui| {
+                ui.monospace("Data:        ");
+                ui.add(egui::TextEdit::singleline(&mut self.data).hint_text("hex"));
+            });
+        } else {
+            ui.horizontal(|ui| {
+                ui.monospace("Value:       ");
+                ui.add(egui::TextEdit::singleline(&mut self.value));
+                ui.monospace("BTC");
+            });
+        }
         ui.horizontal(|ui| {
             ui.monospace("Address:     ");
             ui.add(egui::TextEdit::singleline(&mut self.address));
             if ui.button("generate").clicked() {
-                self.address = app
-                    .wallet
-                    .get_new_address()
-                    .map(|address| format!("{address}"))
-                    .unwrap_or("".into());
+                let address = app.wallet.get_new_address().map_err(Error::from);
+                if let Some(address) = notifications.check("generate address", address) {
+                    self.address = format!("{address}");
+                }
             }
         });
+        if self.utxo_type == UtxoType::Locked {
+            ui.horizontal(|ui| {
+                ui.monospace("Locked until:");
+                ui.add(egui::TextEdit::singleline(&mut self.locked_until).hint_text("height"));
+            });
+        }
         if self.utxo_type == UtxoType::Withdrawal {
             ui.horizontal(|ui| {
                 ui.monospace("Main Address:");
                 ui.add(egui::TextEdit::singleline(&mut self.main_address));
-                if ui.button("generate").clicked() {
-                    let main_address = app.get_new_main_address().unwrap();
-                    self.main_address = format!("{main_address}");
+                if ui
+                    .add_enabled(
+                        self.generating_main_address.is_none(),
+                        egui::Button::new("generate"),
+                    )
+                    .clicked()
+                {
+                    self.generating_main_address =
+                        Some(Task::spawn(app, ui.ctx(), |app| async move {
+                            app.get_new_main_address().await
+                        }));
+                }
+                if self.generating_main_address.is_some() {
+                    ui.spinner();
                 }
             });
+            let main_address: Option<bitcoin::Address<bitcoin::address::NetworkUnchecked>> =
+                self.main_address.parse().ok();
+            if let Some(main_address) = main_address {
+                if app.check_main_address(&main_address).is_err() {
+                    ui.label(format!("Main address is not valid on {}", app.network));
+                }
+            }
             ui.horizontal(|ui| {
                 ui.monospace("Main Fee:    ");
                 ui.add(egui::TextEdit::singleline(&mut self.main_fee));
@@ -101,7 +161,7 @@ impl UtxoCreator {
                             address: address.expect("should not happen"),
                             content: Content::Value(value.expect("should not happen").to_sat()),
                         };
-                        app.transaction.outputs.push(utxo);
+                        transaction.outputs.push(utxo);
                     }
                 }
                 UtxoType::Withdrawal => {
@@ -111,6 +171,8 @@ impl UtxoCreator {
                     let address: Option<ddk::types::Address> = self.address.parse().ok();
                     let main_address: Option<bitcoin::Address<bitcoin::address::NetworkUnchecked>> =
                         self.main_address.parse().ok();
+                    let main_address = main_address
+                        .filter(|main_address| app.check_main_address(main_address).is_ok());
                     let main_fee: Option<bitcoin::Amount> = bitcoin::Amount::from_str_in(
                         &self.main_fee,
                         bitcoin::Denomination::Bitcoin,
@@ -134,12 +196,62 @@ impl UtxoCreator {
                                 main_fee: main_fee.expect("invalid main_fee").to_sat(),
                             },
                         };
-                        app.transaction.outputs.push(utxo);
+                        transaction.outputs.push(utxo);
+                    }
+                }
+                UtxoType::Locked => {
+                    let address: Option<ddk::types::Address> = self.address.parse().ok();
+                    let value: Option<bitcoin::Amount> =
+                        bitcoin::Amount::from_str_in(&self.value, bitcoin::Denomination::Bitcoin)
+                            .ok();
+                    let until: Option<u32> = self.locked_until.parse().ok();
+                    if ui
+                        .add_enabled(
+                            address.is_some() && value.is_some() && until.is_some(),
+                            egui::Button::new("create"),
+                        )
+                        .clicked()
+                    {
+                        let utxo = Output {
+                            address: address.expect("should not happen"),
+                            content: Content::Custom(Thunder::Locked {
+                                value: value.expect("should not happen").to_sat(),
+                                until: until.expect("should not happen"),
+                            }),
+                        };
+                        transaction.outputs.push(utxo);
                     }
                 }
+                UtxoType::Data => {
+                    let address: Option<ddk::types::Address> = self.address.parse().ok();
+                    let data: Option<Vec<u8>> = hex::decode(&self.data).ok();
+                    let height = app.node.get_height().unwrap_or(0) + 1;
+                    let data_size_limit = ThunderState::data_size_limit(height);
+                    let data = data.filter(|data| data.len() as u64 <= data_size_limit);
+                    if ui
+                        .add_enabled(
+                            address.is_some() && data.is_some(),
+                            egui::Button::new("create"),
+                        )
+                        .clicked()
+                    {
+                        let utxo = Output {
+                            address: address.expect("should not happen"),
+                            content: Content::Custom(Thunder::Data(
+                                data.expect("should not happen"),
+                            )),
+                        };
+                        transaction.outputs.push(utxo);
+                    }
+                    ui.label(format!(
+                        "at most {data_size_limit} bytes, {} sats fee per byte",
+                        ThunderState::data_fee_rate(height)
+                    ));
+                }
+            }
+            if let Ok(num_addresses) = app.wallet.get_num_addresses() {
+                ui.label(format!("{num_addresses} addresses generated"));
             }
-            let num_addresses = app.wallet.get_num_addresses().unwrap();
-            ui.label(format!("{num_addresses} addresses generated"));
         });
     }
 }
@@ -1,24 +1,58 @@
-use crate::app::App;
+use crate::app::{self, App, Error};
+use crate::coin_selection::{Selection, Strategy};
 use crate::thunder::Thunder;
 use ddk::bitcoin;
-use ddk::types::{GetValue, OutPoint, Output};
+use ddk::types::{GetValue, OutPoint, Output, Transaction};
 use eframe::egui;
 use std::collections::HashSet;
 
+use super::change::Change;
+use super::notifications::Notifications;
+
 #[derive(Default)]
-pub struct UtxoSelector;
+pub struct UtxoSelector {
+    strategy: Strategy,
+}
 
 impl UtxoSelector {
-    pub fn show(&mut self, app: &mut App, ui: &mut egui::Ui) {
+    pub fn show(
+        &mut self,
+        app: &mut App,
+        transaction: &mut Transaction<Thunder>,
+        change: &Change,
+        notifications: &mut Notifications,
+        ui: &mut egui::Ui,
+    ) {
         ui.heading("Spend UTXO");
-        let selected: HashSet<_> = app.transaction.inputs.iter().cloned().collect();
-        let utxos = &app.utxos;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("coin_selection")
+                .selected_text(format!("{}", self.strategy))
+                .show_ui(ui, |ui| {
+                    for strategy in Strategy::ALL {
+                        ui.selectable_value(&mut self.strategy, strategy, format!("{strategy}"));
+                    }
+                });
+            if ui.button("auto-select inputs").clicked() {
+                let selection = self.select_inputs(app, transaction, change);
+                if let Some(selection) = notifications.check("auto-select inputs", selection) {
+                    transaction.inputs = selection.inputs;
+                }
+            }
+        });
+        let selected: HashSet<_> = transaction.inputs.iter().cloned().collect();
+        let height = app.node.get_height().unwrap_or(0) + 1;
+        let blocks_left = |outpoint: &OutPoint, output: &Output<Thunder>| {
+            app.blocks_until_spendable(outpoint, output, height)
+        };
+        let utxos = app.utxos.read().unwrap();
         let total: u64 = utxos
             .iter()
-            .filter(|(outpoint, _)| !selected.contains(outpoint))
+            .filter(|(outpoint, output)| {
+                !selected.contains(outpoint) && matches!(blocks_left(outpoint, output), Ok(None))
+            })
             .map(|(_, output)| output.get_value())
             .sum();
-        let mut utxos: Vec<_> = utxos.into_iter().collect();
+        let mut utxos: Vec<_> = utxos.iter().collect();
         utxos.sort_by_key(|(outpoint, _)| format!("{outpoint}"));
         ui.separator();
         ui.monospace(format!("Total: {}", bitcoin::Amount::from_sat(total)));
@@ -33,18 +67,67 @@ impl UtxoSelector {
                     continue;
                 }
                 //ui.horizontal(|ui| {});
+                let blocks_left = blocks_left(outpoint, output);
+                // Outputs that can't be spent yet are greyed out, the grid needs the cells in
+                // this same ui.
+                let text_color = ui.visuals().override_text_color;
+                if !matches!(blocks_left, Ok(None)) {
+                    ui.visuals_mut().override_text_color = Some(ui.visuals().weak_text_color());
+                }
                 show_utxo(ui, outpoint, output);
-
-                if ui
-                    .add_enabled(!selected.contains(outpoint), egui::Button::new("spend"))
-                    .clicked()
-                {
-                    app.transaction.inputs.push(*outpoint);
+                ui.visuals_mut().override_text_color = text_color;
+                match blocks_left {
+                    Ok(Some(blocks_left)) => {
+                        ui.weak(format!("immature, {blocks_left} blocks left"));
+                    }
+                    Err(err) => {
+                        ui.weak("maturity unknown")
+                            .on_hover_text(app::format_error_chain(&err));
+                    }
+                    Ok(None) => {
+                        if ui
+                            .add_enabled(!selected.contains(outpoint), egui::Button::new("spend"))
+                            .clicked()
+                        {
+                            transaction.inputs.push(*outpoint);
+                        }
+                    }
                 }
                 ui.end_row();
             }
         });
     }
+
+    /// Inputs for the outputs of `transaction` and the fee that `change` takes for them. The fee
+    /// grows with the number of inputs, so the selection is repeated until it covers the fee for
+    /// its own inputs.
+    fn select_inputs(
+        &self,
+        app: &App,
+        transaction: &Transaction<Thunder>,
+        change: &Change,
+    ) -> Result<Selection, Error> {
+        let value_out = transaction
+            .outputs
+            .iter()
+            .map(GetValue::get_value)
+            .try_fold(0, u64::checked_add)
+            .ok_or(Error::ValueOverflow)?;
+        let mut fee = 0;
+        loop {
+            let target = value_out.checked_add(fee).ok_or(Error::ValueOverflow)?;
+            let selection = app.select_inputs(self.strategy, target)?;
+            let selected = Transaction {
+                inputs: selection.inputs.clone(),
+                outputs: transaction.outputs.clone(),
+            };
+            let required = change.get_fee(&selected)?.unwrap_or(0);
+            if required <= fee {
+                return Ok(selection);
+            }
+            fee = required;
+        }
+    }
 }
 
 pub fn show_utxo(ui: &mut egui::Ui, outpoint: &OutPoint, output: &Output<Thunder>) {
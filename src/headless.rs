@@ -0,0 +1,33 @@
+use crate::app::App;
+use jsonrpsee::server::ServerHandle;
+
+pub fn run(
+    app: App,
+    runtime: tokio::runtime::Runtime,
+    rpc_server: ServerHandle,
+) -> anyhow::Result<()> {
+    eprintln!("Thunder node running headless, press Ctrl-C to stop");
+    app.block_on(shutdown_signal())?;
+    eprintln!("Shutting down");
+    rpc_server.stop()?;
+    app.block_on(rpc_server.stopped());
+    // Dropping the runtime stops p2p tasks, dropping the app closes the databases.
+    drop(runtime);
+    drop(app);
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = sigterm.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
@@ -0,0 +1,552 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::thunder::{self, Node, Thunder};
+use ddk::authorization::Authorization;
+use ddk::heed;
+use ddk::heed::types::{ByteSlice, OwnedType, SerdeBincode, Str};
+use ddk::types::{Address, BlockHash, Body, GetValue, MerkleRoot, OutPoint, Output, Txid};
+use serde::{Deserialize, Serialize};
+
+/// Where a confirmed transaction is, `index` is its position in `Body::transactions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TransactionLocation {
+    pub height: u32,
+    pub index: u32,
+}
+
+/// Last indexed block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct Tip {
+    height: u32,
+    hash: BlockHash,
+}
+
+/// What connecting the body at some height added to the index, so that it can be taken out again.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Undo {
+    /// Tip before the body was connected.
+    prev_tip: Option<Tip>,
+    merkle_root: Option<MerkleRoot>,
+    txids: Vec<Txid>,
+    history_keys: Vec<Vec<u8>>,
+    /// Value that the body paid to each address.
+    received: Vec<(Address, u64)>,
+}
+
+/// Transaction and address indexes, kept in a database of their own next to the node's and
+/// filled from the bodies that the node connected, starting at genesis.
+#[derive(Clone)]
+pub struct Index {
+    env: heed::Env,
+    /// Only has the `TIP` key.
+    tip: heed::Database<Str, SerdeBincode<Tip>>,
+    transactions: heed::Database<SerdeBincode<Txid>, SerdeBincode<TransactionLocation>>,
+    /// One entry for every output ever paid to an address, see `history_key`.
+    history: heed::Database<ByteSlice, SerdeBincode<OutPoint>>,
+    /// Height of the block that created the coinbase outputs with a merkle root.
+    coinbases: heed::Database<SerdeBincode<MerkleRoot>, OwnedType<u32>>,
+    /// Total value of the outputs paid to an address in indexed bodies.
+    received: heed::Database<SerdeBincode<Address>, OwnedType<u64>>,
+    /// Value of every deposit recorded with `record_deposits`, by the address followed by the
+    /// outpoint. Deposits come from the mainchain rather than from bodies, so they are kept when
+    /// bodies are taken out.
+    deposits: heed::Database<ByteSlice, OwnedType<u64>>,
+    /// Only kept for the last `UNDO_DEPTH` bodies.
+    undo: heed::Database<OwnedType<u32>, SerdeBincode<Undo>>,
+}
+
+impl Index {
+    const TIP: &'static str = "tip";
+    /// How many bodies are indexed before the write transaction is committed, so that indexing a
+    /// long chain doesn't hold everything in one transaction.
+    const SYNC_BATCH_SIZE: u32 = 1000;
+    /// How many of the last indexed bodies can be taken out on a reorg, a deeper reorg indexes
+    /// the chain again from genesis.
+    const UNDO_DEPTH: u32 = 100;
+
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        std::fs::create_dir_all(path)?;
+        let env = heed::EnvOpenOptions::new()
+            .map_size(1024 * 1024 * 1024)
+            .max_dbs(7)
+            .open(path)?;
+        // An index from before received values were indexed is missing them for the bodies it
+        // already has, and its undo data has a different format, so it is built again.
+        let reindex = env
+            .open_database::<SerdeBincode<Address>, OwnedType<u64>>(Some("received"))?
+            .is_none();
+        let tip = env.create_database(Some("tip"))?;
+        let transactions = env.create_database(Some("transactions"))?;
+        let history = env.create_database(Some("history"))?;
+        let coinbases = env.create_database(Some("coinbases"))?;
+        let received = env.create_database(Some("received"))?;
+        let deposits = env.create_database(Some("deposits"))?;
+        let undo = env.create_database(Some("undo"))?;
+        let index = Self {
+            env,
+            tip,
+            transactions,
+            history,
+            coinbases,
+            received,
+            deposits,
+            undo,
+        };
+        if reindex {
+            let mut txn = index.env.write_txn()?;
+            index.clear(&mut txn)?;
+            txn.commit()?;
+        }
+        Ok(index)
+    }
+
+    /// Catch up with the node: take out bodies that are no longer in its chain, then index the
+    /// ones that are missing. The first sync indexes the whole chain.
+    pub fn sync(&self, node: &Node) -> Result<(), Error> {
+        let mut txn = self.env.write_txn()?;
+        while let Some(tip) = self.get_tip(&txn)? {
+            let hash = node.get_header(tip.height)?.map(|header| header.hash());
+            if hash == Some(tip.hash) {
+                break;
+            }
+            match self.disconnect_tip(&mut txn) {
+                Err(Error::NoUndoData { .. }) => {
+                    self.clear(&mut txn)?;
+                    break;
+                }
+                result => result?,
+            }
+        }
+        let start = match self.get_tip(&txn)? {
+            Some(tip) => tip.height + 1,
+            None => 0,
+        };
+        let node_height = node.get_height()?;
+        for height in start..=node_height {
+            let (Some(header), Some(body)) = (node.get_header(height)?, node.get_body(height)?)
+            else {
+                continue;
+            };
+            self.connect_body(&mut txn, height, header.hash(), &body)?;
+            if (height - start + 1) % Self::SYNC_BATCH_SIZE == 0 {
+                txn.commit()?;
+                txn = self.env.write_txn()?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    pub fn connect_body(
+        &self,
+        txn: &mut heed::RwTxn,
+        height: u32,
+        hash: BlockHash,
+        body: &Body<Authorization, Thunder>,
+    ) -> Result<(), Error> {
+        let mut undo = Undo {
+            prev_tip: self.get_tip(txn)?,
+            ..Undo::default()
+        };
+        let merkle_root = body.compute_merkle_root();
+        if !body.coinbase.is_empty() {
+            self.coinbases.put(txn, &merkle_root, &height)?;
+            undo.merkle_root = Some(merkle_root);
+        }
+        let coinbase_outputs = body.coinbase.iter().enumerate().map(|(vout, output)| {
+            let outpoint = OutPoint::Coinbase {
+                merkle_root,
+                vout: vout as u32,
+            };
+            (outpoint, output)
+        });
+        let transaction_outputs = body.transactions.iter().flat_map(|transaction| {
+            let txid = transaction.txid();
+            transaction
+                .outputs
+                .iter()
+                .enumerate()
+                .map(move |(vout, output)| {
+                    let outpoint = OutPoint::Regular {
+                        txid,
+                        vout: vout as u32,
+                    };
+                    (outpoint, output)
+                })
+        });
+        let mut received: HashMap<Address, u64> = HashMap::new();
+        for (position, (outpoint, output)) in
+            coinbase_outputs.chain(transaction_outputs).enumerate()
+        {
+            let key = history_key(&output.address, height, position as u32)?;
+            self.history.put(txn, &key, &outpoint)?;
+            undo.history_keys.push(key);
+            let value = received.entry(output.address).or_default();
+            *value = value.saturating_add(output.get_value());
+        }
+        // Addresses that were paid nothing get no entry, so that an entry only goes away when
+        // its total drops back to zero.
+        for (address, value) in received.into_iter().filter(|(_, value)| *value > 0) {
+            let total = self.received.get(txn, &address)?.unwrap_or(0);
+            self.received
+                .put(txn, &address, &total.saturating_add(value))?;
+            undo.received.push((address, value));
+        }
+        for (index, transaction) in body.transactions.iter().enumerate() {
+            let txid = transaction.txid();
+            let location = TransactionLocation {
+                height,
+                index: index as u32,
+            };
+            self.transactions.put(txn, &txid, &location)?;
+            undo.txids.push(txid);
+        }
+        self.undo.put(txn, &height, &undo)?;
+        if let Some(prune_height) = height.checked_sub(Self::UNDO_DEPTH) {
+            self.undo.delete(txn, &prune_height)?;
+        }
+        self.tip.put(txn, Self::TIP, &Tip { height, hash })?;
+        Ok(())
+    }
+
+    /// Take the last indexed body back out of the index.
+    pub fn disconnect_tip(&self, txn: &mut heed::RwTxn) -> Result<(), Error> {
+        let Some(tip) = self.get_tip(txn)? else {
+            return Ok(());
+        };
+        let undo = self
+            .undo
+            .get(txn, &tip.height)?
+            .ok_or(Error::NoUndoData { height: tip.height })?;
+        if let Some(merkle_root) = &undo.merkle_root {
+            self.coinbases.delete(txn, merkle_root)?;
+        }
+        for txid in &undo.txids {
+            self.transactions.delete(txn, txid)?;
+        }
+        for key in &undo.history_keys {
+            self.history.delete(txn, key)?;
+        }
+        for (address, value) in &undo.received {
+            let total = self.received.get(txn, address)?.unwrap_or(0);
+            match total.saturating_sub(*value) {
+                0 => {
+                    self.received.delete(txn, address)?;
+                }
+                total => self.received.put(txn, address, &total)?,
+            }
+        }
+        self.undo.delete(txn, &tip.height)?;
+        match undo.prev_tip {
+            Some(prev_tip) => self.tip.put(txn, Self::TIP, &prev_tip)?,
+            None => {
+                self.tip.delete(txn, Self::TIP)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Take out everything that was indexed from bodies, recorded deposits stay.
+    fn clear(&self, txn: &mut heed::RwTxn) -> Result<(), Error> {
+        self.tip.clear(txn)?;
+        self.transactions.clear(txn)?;
+        self.history.clear(txn)?;
+        self.coinbases.clear(txn)?;
+        self.received.clear(txn)?;
+        self.undo.clear(txn)?;
+        Ok(())
+    }
+
+    /// Remember the value of deposits, so that they still count towards what their address
+    /// received once they are spent. Outputs other than deposits are skipped.
+    pub fn record_deposits<'a>(
+        &self,
+        utxos: impl IntoIterator<Item = (&'a OutPoint, &'a Output<Thunder>)>,
+    ) -> Result<(), Error> {
+        let mut txn = self.env.write_txn()?;
+        for (outpoint, output) in utxos {
+            if !matches!(outpoint, OutPoint::Deposit(_)) {
+                continue;
+            }
+            let mut key = bincode::serialize(&output.address)?;
+            key.extend(bincode::serialize(outpoint)?);
+            self.deposits.put(&mut txn, &key, &output.get_value())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn get_tip(&self, txn: &heed::RoTxn) -> Result<Option<Tip>, Error> {
+        Ok(self.tip.get(txn, Self::TIP)?)
+    }
+
+    /// Height of the last indexed body.
+    pub fn get_height(&self) -> Result<Option<u32>, Error> {
+        let txn = self.env.read_txn()?;
+        Ok(self.get_tip(&txn)?.map(|tip| tip.height))
+    }
+
+    pub fn read_txn(&self) -> Result<heed::RoTxn, Error> {
+        Ok(self.env.read_txn()?)
+    }
+
+    /// Height of the block whose coinbase has `merkle_root`.
+    pub fn get_coinbase_height(
+        &self,
+        txn: &heed::RoTxn,
+        merkle_root: &MerkleRoot,
+    ) -> Result<Option<u32>, Error> {
+        Ok(self.coinbases.get(txn, merkle_root)?)
+    }
+
+    pub fn get_transaction_location(
+        &self,
+        txid: &Txid,
+    ) -> Result<Option<TransactionLocation>, Error> {
+        let txn = self.env.read_txn()?;
+        Ok(self.transactions.get(&txn, txid)?)
+    }
+
+    /// Total value paid to `address` in indexed bodies and in recorded deposits.
+    pub fn get_received_value(&self, address: &Address) -> Result<u64, Error> {
+        let txn = self.env.read_txn()?;
+        let mut received = self.received.get(&txn, address)?.unwrap_or(0);
+        let prefix = bincode::serialize(address)?;
+        for entry in self.deposits.prefix_iter(&txn, &prefix)? {
+            received = received.saturating_add(entry?.1);
+        }
+        Ok(received)
+    }
+
+    pub fn get_address_history(&self, address: &Address) -> Result<Vec<OutPoint>, Error> {
+        let txn = self.env.read_txn()?;
+        let prefix = bincode::serialize(address)?;
+        self.history
+            .prefix_iter(&txn, &prefix)?
+            .map(|entry| Ok(entry?.1))
+            .collect()
+    }
+}
+
+/// The address followed by the big endian height and the position of the output in its block, so
+/// that the history of an address is a range of keys, oldest first, and every output has a key of
+/// its own.
+fn history_key(address: &Address, height: u32, position: u32) -> Result<Vec<u8>, Error> {
+    let mut key = bincode::serialize(address)?;
+    key.extend_from_slice(&height.to_be_bytes());
+    key.extend_from_slice(&position.to_be_bytes());
+    Ok(key)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("bincode error")]
+    Bincode(#[from] bincode::Error),
+    #[error("heed error")]
+    Heed(#[from] heed::Error),
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+    #[error("node error")]
+    Node(#[from] ddk::node::Error<thunder::Error>),
+    #[error("no undo data for the body at height {height}")]
+    NoUndoData { height: u32 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ddk::types::{Content, Output, Transaction};
+
+    type Dump = Vec<(Vec<u8>, Vec<u8>)>;
+
+    fn body(height: u32) -> Body<Authorization, Thunder> {
+        let output = |byte: u8, value: u64| Output {
+            address: Address::from([byte; 20]),
+            content: Content::Value(value),
+        };
+        let transaction = Transaction {
+            inputs: vec![],
+            outputs: vec![output(1, height as u64), output(100 + height as u8, 1)],
+        };
+        Body {
+            coinbase: vec![output(0, 1000 + height as u64)],
+            transactions: vec![transaction],
+            authorizations: vec![],
+        }
+    }
+
+    fn hash(height: u32) -> BlockHash {
+        BlockHash::from([height as u8; 32])
+    }
+
+    fn dump(index: &Index) -> Vec<Dump> {
+        fn dump_db<K, V>(txn: &heed::RoTxn, db: heed::Database<K, V>) -> Dump {
+            db.remap_types::<ByteSlice, ByteSlice>()
+                .iter(txn)
+                .unwrap()
+                .map(|entry| {
+                    let (key, value) = entry.unwrap();
+                    (key.to_vec(), value.to_vec())
+                })
+                .collect()
+        }
+        let txn = index.read_txn().unwrap();
+        vec![
+            dump_db(&txn, index.tip),
+            dump_db(&txn, index.transactions),
+            dump_db(&txn, index.history),
+            dump_db(&txn, index.coinbases),
+            dump_db(&txn, index.received),
+            dump_db(&txn, index.deposits),
+            dump_db(&txn, index.undo),
+        ]
+    }
+
+    fn connect(index: &Index, heights: std::ops::Range<u32>) {
+        let mut txn = index.env.write_txn().unwrap();
+        for height in heights {
+            index
+                .connect_body(&mut txn, height, hash(height), &body(height))
+                .unwrap();
+        }
+        txn.commit().unwrap();
+    }
+
+    #[test]
+    fn disconnect_tip_restores_previous_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = Index::open(dir.path()).unwrap();
+        const N: u32 = 5;
+        connect(&index, 0..N - 1);
+        let before_tip = dump(&index);
+        connect(&index, N - 1..N);
+        let tip = body(N - 1);
+        let txid = tip.transactions[0].txid();
+        let address = Address::from([1; 20]);
+        assert_eq!(
+            index.get_transaction_location(&txid).unwrap(),
+            Some(TransactionLocation {
+                height: N - 1,
+                index: 0
+            })
+        );
+        assert_eq!(
+            index.get_address_history(&address).unwrap().len(),
+            N as usize
+        );
+        let received: u64 = (0..N as u64).sum();
+        assert_eq!(index.get_received_value(&address).unwrap(), received);
+        let mut txn = index.env.write_txn().unwrap();
+        index.disconnect_tip(&mut txn).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(dump(&index), before_tip);
+        assert_eq!(index.get_transaction_location(&txid).unwrap(), None);
+        let history = index.get_address_history(&address).unwrap();
+        assert_eq!(history.len(), N as usize - 1);
+        assert!(!history.contains(&OutPoint::Regular { txid, vout: 0 }));
+        let received = received - (N as u64 - 1);
+        assert_eq!(index.get_received_value(&address).unwrap(), received);
+        let txn = index.read_txn().unwrap();
+        let merkle_root = tip.compute_merkle_root();
+        assert_eq!(index.get_coinbase_height(&txn, &merkle_root).unwrap(), None);
+        assert_eq!(
+            index.get_tip(&txn).unwrap(),
+            Some(Tip {
+                height: N - 2,
+                hash: hash(N - 2)
+            })
+        );
+    }
+
+    #[test]
+    fn disconnecting_every_body_restores_every_previous_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = Index::open(dir.path()).unwrap();
+        const N: u32 = 5;
+        let mut snapshots = vec![];
+        for height in 0..N {
+            snapshots.push(dump(&index));
+            connect(&index, height..height + 1);
+        }
+        for height in (0..N).rev() {
+            let mut txn = index.env.write_txn().unwrap();
+            index.disconnect_tip(&mut txn).unwrap();
+            txn.commit().unwrap();
+            assert_eq!(
+                dump(&index),
+                snapshots[height as usize],
+                "after disconnecting height {height}"
+            );
+        }
+        assert!(dump(&index).iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn recorded_deposits_count_once_and_survive_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = Index::open(dir.path()).unwrap();
+        connect(&index, 0..3);
+        let address = Address::from([1; 20]);
+        let deposit = |vout: u32, value: u64| {
+            let outpoint = OutPoint::Deposit(ddk::bitcoin::OutPoint {
+                vout,
+                ..ddk::bitcoin::OutPoint::null()
+            });
+            let output = Output {
+                address,
+                content: Content::Value(value),
+            };
+            (outpoint, output)
+        };
+        let regular = OutPoint::Regular {
+            txid: body(0).transactions[0].txid(),
+            vout: 0,
+        };
+        let utxos = [
+            deposit(0, 500),
+            deposit(1, 700),
+            (regular, body(0).transactions[0].outputs[0].clone()),
+        ];
+        index
+            .record_deposits(utxos.iter().map(|(outpoint, output)| (outpoint, output)))
+            .unwrap();
+        // Seeing a deposit again doesn't count it twice.
+        index
+            .record_deposits(
+                utxos[..1]
+                    .iter()
+                    .map(|(outpoint, output)| (outpoint, output)),
+            )
+            .unwrap();
+        // Bodies 0, 1 and 2 paid their height to the address.
+        let from_bodies = 1 + 2;
+        assert_eq!(
+            index.get_received_value(&address).unwrap(),
+            from_bodies + 500 + 700
+        );
+        let mut txn = index.env.write_txn().unwrap();
+        index.clear(&mut txn).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(index.get_received_value(&address).unwrap(), 500 + 700);
+    }
+
+    #[test]
+    fn undo_data_is_pruned_past_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = Index::open(dir.path()).unwrap();
+        let num_bodies = Index::UNDO_DEPTH + 10;
+        connect(&index, 0..num_bodies);
+        let txn = index.read_txn().unwrap();
+        assert_eq!(index.undo.len(&txn).unwrap(), Index::UNDO_DEPTH as u64);
+        drop(txn);
+        let mut txn = index.env.write_txn().unwrap();
+        for _ in 10..num_bodies {
+            index.disconnect_tip(&mut txn).unwrap();
+        }
+        assert!(matches!(
+            index.disconnect_tip(&mut txn),
+            Err(Error::NoUndoData { height: 9 })
+        ));
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use ddk::types::Address;
+
+/// Names that the user gave to wallet addresses, kept in a JSON file so that they survive
+/// restarts. The wallet database belongs to ddk, so they can't be stored next to the keys.
+#[derive(Debug)]
+pub struct Labels {
+    path: PathBuf,
+    /// Keyed by the address string, JSON object keys have to be strings.
+    labels: BTreeMap<String, String>,
+}
+
+impl Labels {
+    /// Read the labels from `path`, a missing file means that there are none yet.
+    pub fn load(path: &Path) -> Result<Self, std::io::Error> {
+        let labels = match std::fs::read(path) {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            labels,
+        })
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&str> {
+        self.labels.get(&format!("{address}")).map(String::as_str)
+    }
+
+    /// Label `address` and save all labels, an empty label removes it.
+    pub fn set(&mut self, address: &Address, label: &str) -> Result<(), std::io::Error> {
+        let label = label.trim();
+        if label.is_empty() {
+            self.labels.remove(&format!("{address}"));
+        } else {
+            self.labels.insert(format!("{address}"), label.into());
+        }
+        // Write to a temporary file first, so that a crash can't leave half of the labels behind.
+        let json = serde_json::to_vec_pretty(&self.labels)?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, json)?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
@@ -0,0 +1,17 @@
+pub mod app;
+pub mod auto_miner;
+pub mod block_template;
+pub mod bribe;
+pub mod cli;
+pub mod coin_selection;
+pub mod coinbase;
+pub mod fee_estimator;
+pub mod gui;
+pub mod headless;
+pub mod index;
+pub mod labels;
+pub mod main_proxy;
+pub mod params;
+pub mod policy;
+pub mod rpc;
+pub mod thunder;
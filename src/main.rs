@@ -1,14 +1,46 @@
 use clap::Parser as _;
-
-mod app;
-mod cli;
-mod gui;
-mod thunder;
+use thunder::{app, auto_miner, cli, gui, headless, params, rpc};
 
 fn main() -> anyhow::Result<()> {
     let cli = cli::Cli::parse();
     let config = cli.get_config()?;
-    let app = app::App::new(&config)?;
+    if cli.print_config {
+        print!("{config}");
+        return Ok(());
+    }
+    // The limits are looked up by the node during validation, so they have to be in place before
+    // it starts.
+    params::set_schedule(config.schedule.clone())?;
+    // Node launches some tokio tasks for p2p networking and the RPC server runs on tokio too, that
+    // is why we need a tokio runtime here.
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    if cli.reindex {
+        app::remove_index(&config.datadir)?;
+    }
+    let app = app::App::new(&config, runtime.handle().clone())?;
+    if cli.reindex {
+        app.rebuild_state(&config.datadir)?;
+    }
+    let (rpc_user, rpc_password) = match (&config.rpc_user, &config.rpc_password) {
+        (Some(rpc_user), Some(rpc_password)) => (rpc_user.clone(), rpc_password.clone()),
+        _ => rpc::write_cookie(&config.datadir)?,
+    };
+    let rpc_server = runtime.block_on(rpc::run_server(
+        app.clone(),
+        config.rpc_addr,
+        &rpc_user,
+        &rpc_password,
+    ))?;
+
+    if cli.headless {
+        if let Some(auto_miner_config) = cli.get_auto_miner_config() {
+            // Stopped when the runtime shuts down.
+            auto_miner::AutoMiner::start(&app, auto_miner_config);
+        }
+        return headless::run(app, runtime, rpc_server);
+    }
 
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
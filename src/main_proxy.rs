@@ -0,0 +1,131 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use base64::Engine as _;
+use hyper::client::HttpConnector;
+use hyper::header::{self, HeaderValue};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Request, Response, Server, StatusCode};
+
+/// Read mainchain RPC credentials from a bitcoind style `user:password` cookie file.
+pub fn read_cookie(path: &Path) -> Result<(String, String), Error> {
+    let cookie = std::fs::read_to_string(path)?;
+    let (user, password) = cookie
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| Error::InvalidCookie(path.to_path_buf()))?;
+    Ok((user.into(), password.into()))
+}
+
+const PROXY_USER: &str = "__proxy__";
+
+/// Address of a running proxy and the credentials that it accepts.
+pub struct Proxy {
+    pub addr: SocketAddr,
+    pub user: String,
+    pub password: String,
+}
+
+/// Start a proxy on a local port that forwards RPC requests to the mainchain node at `main_addr`,
+/// with credentials read from `main_cookie` for every request.
+///
+/// The mainchain node writes a new cookie every time it starts. The node and the miner keep the
+/// address and credentials they were created with, so they talk to the proxy instead, and pick up
+/// a new cookie without being rebuilt. The proxy only accepts requests with a password that is
+/// generated for this process, so that other local users can't borrow the mainchain credentials.
+/// Has to be called from inside of the tokio runtime.
+pub fn spawn(main_addr: SocketAddr, main_cookie: PathBuf) -> Result<Proxy, Error> {
+    // Fail right away on a missing or invalid cookie, rather than on the first request.
+    read_cookie(&main_cookie)?;
+    let password = hex::encode(rand::random::<[u8; 32]>());
+    let expected = basic_authorization(PROXY_USER, &password)
+        .map_err(|_| Error::InvalidCookie(main_cookie.clone()))?;
+    let client = Client::new();
+    let make_service = make_service_fn(move |_| {
+        let client = client.clone();
+        let main_cookie = main_cookie.clone();
+        let expected = expected.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                forward(
+                    client.clone(),
+                    main_addr,
+                    main_cookie.clone(),
+                    expected.clone(),
+                    request,
+                )
+            }))
+        }
+    });
+    let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))?.serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    Ok(Proxy {
+        addr,
+        user: PROXY_USER.into(),
+        password,
+    })
+}
+
+fn basic_authorization(
+    user: &str,
+    password: &str,
+) -> Result<HeaderValue, header::InvalidHeaderValue> {
+    let credentials =
+        base64::engine::general_purpose::STANDARD.encode(format!("{user}:{password}"));
+    HeaderValue::from_str(&format!("Basic {credentials}"))
+}
+
+async fn forward(
+    client: Client<HttpConnector>,
+    main_addr: SocketAddr,
+    main_cookie: PathBuf,
+    expected: HeaderValue,
+    mut request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if request.headers().get(header::AUTHORIZATION) != Some(&expected) {
+        let mut response = Response::new(Body::from("mainchain proxy: unauthorized"));
+        *response.status_mut() = StatusCode::UNAUTHORIZED;
+        return Ok(response);
+    }
+    let (user, password) = match read_cookie(&main_cookie) {
+        Ok(credentials) => credentials,
+        Err(err) => return Ok(bad_gateway(&err)),
+    };
+    let authorization = match basic_authorization(&user, &password) {
+        Ok(authorization) => authorization,
+        Err(_) => return Ok(bad_gateway(&Error::InvalidCookie(main_cookie))),
+    };
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str());
+    *request.uri_mut() = match format!("http://{main_addr}{path}").parse() {
+        Ok(uri) => uri,
+        Err(err) => return Ok(bad_gateway(&err)),
+    };
+    let headers = request.headers_mut();
+    headers.remove(header::HOST);
+    headers.insert(header::AUTHORIZATION, authorization);
+    match client.request(request).await {
+        Ok(response) => Ok(response),
+        Err(err) => Ok(bad_gateway(&err)),
+    }
+}
+
+fn bad_gateway(err: &dyn std::error::Error) -> Response<Body> {
+    let mut response = Response::new(Body::from(format!("mainchain proxy: {err}")));
+    *response.status_mut() = StatusCode::BAD_GATEWAY;
+    response
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+    #[error("invalid mainchain cookie file {0}")]
+    InvalidCookie(PathBuf),
+    #[error("hyper error")]
+    Hyper(#[from] hyper::Error),
+}
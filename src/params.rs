@@ -0,0 +1,272 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+/// Consensus limits in effect from some height on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Limits {
+    pub transaction_size_limit: u64,
+    pub transaction_sigops_limit: u64,
+    pub body_size_limit: u64,
+    pub body_sigops_limit: u64,
+    /// Maximum number of bytes in a single data output.
+    pub data_size_limit: u64,
+    /// Sats of fee that a transaction has to pay for every byte in its data outputs.
+    pub data_fee_rate: u64,
+    /// Blocks that the outputs of coinbases created at this height have to wait before they can
+    /// be spent, 0 if they can be spent right away. 0 by default, so that chains that ran before
+    /// there was coinbase maturity keep their coinbases spendable.
+    pub coinbase_maturity: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            transaction_size_limit: 1024 * 1024,
+            transaction_sigops_limit: 8 * 1024,
+            body_size_limit: 8 * 1024 * 1024,
+            body_sigops_limit: 8 * 8 * 1024,
+            data_size_limit: 80,
+            data_fee_rate: 10,
+            coinbase_maturity: 0,
+        }
+    }
+}
+
+/// Limits that change at `height`, the ones that are not set stay as they were.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Activation {
+    height: u32,
+    transaction_size_limit: Option<u64>,
+    transaction_sigops_limit: Option<u64>,
+    body_size_limit: Option<u64>,
+    body_sigops_limit: Option<u64>,
+    data_size_limit: Option<u64>,
+    data_fee_rate: Option<u64>,
+    coinbase_maturity: Option<u32>,
+}
+
+/// Contents of a chain spec file, for example:
+///
+/// ```toml
+/// [[activation]]
+/// height = 100000
+/// body_size_limit = 16777216
+/// ```
+///
+/// Coinbase maturity is off at genesis, a chain turns it on with an activation that sets
+/// `coinbase_maturity`, for example to 100 like on the mainchain, at a height above its tip.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainSpec {
+    #[serde(default)]
+    activation: Vec<Activation>,
+}
+
+/// Limits by activation height, sorted by height and starting at height 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    limits: Vec<(u32, Limits)>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            limits: vec![(0, Limits::default())],
+        }
+    }
+}
+
+impl Schedule {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let chain_spec: ChainSpec = toml::from_str(&contents)?;
+        Self::from_activations(chain_spec.activation)
+    }
+
+    fn from_activations(mut activations: Vec<Activation>) -> anyhow::Result<Self> {
+        activations.sort_by_key(|activation| activation.height);
+        if let Some(pair) = activations
+            .windows(2)
+            .find(|pair| pair[0].height == pair[1].height)
+        {
+            anyhow::bail!("more than one activation at height {}", pair[0].height);
+        }
+        let mut schedule = Self::default();
+        for activation in activations {
+            let (_, last_limits) = *schedule.limits.last().expect("should not happen");
+            let limits = Limits {
+                transaction_size_limit: activation
+                    .transaction_size_limit
+                    .unwrap_or(last_limits.transaction_size_limit),
+                transaction_sigops_limit: activation
+                    .transaction_sigops_limit
+                    .unwrap_or(last_limits.transaction_sigops_limit),
+                body_size_limit: activation
+                    .body_size_limit
+                    .unwrap_or(last_limits.body_size_limit),
+                body_sigops_limit: activation
+                    .body_sigops_limit
+                    .unwrap_or(last_limits.body_sigops_limit),
+                data_size_limit: activation
+                    .data_size_limit
+                    .unwrap_or(last_limits.data_size_limit),
+                data_fee_rate: activation
+                    .data_fee_rate
+                    .unwrap_or(last_limits.data_fee_rate),
+                coinbase_maturity: activation
+                    .coinbase_maturity
+                    .unwrap_or(last_limits.coinbase_maturity),
+            };
+            if activation.height == 0 {
+                schedule.limits[0].1 = limits;
+                continue;
+            }
+            schedule.limits.push((activation.height, limits));
+        }
+        Ok(schedule)
+    }
+
+    pub fn limits(&self, height: u32) -> Limits {
+        let index = self
+            .limits
+            .partition_point(|(activation_height, _)| *activation_height <= height);
+        self.limits[index.saturating_sub(1)].1
+    }
+
+    /// Lowest height whose coinbases have to mature, `None` if no coinbase ever has to.
+    pub fn coinbase_maturity_activation(&self) -> Option<u32> {
+        self.limits
+            .iter()
+            .find(|(_, limits)| limits.coinbase_maturity > 0)
+            .map(|(height, _)| *height)
+    }
+}
+
+static SCHEDULE: OnceLock<Schedule> = OnceLock::new();
+
+/// Install the schedule used by `ThunderState`, this has to happen exactly once, before the node
+/// is started. ddk creates `ThunderState` itself, so the schedule can't be handed to it directly.
+pub fn set_schedule(schedule: Schedule) -> anyhow::Result<()> {
+    SCHEDULE
+        .set(schedule)
+        .map_err(|_| anyhow::anyhow!("the consensus schedule is already set"))
+}
+
+/// The installed schedule, `None` if `set_schedule` wasn't called yet.
+pub fn get_schedule() -> Option<&'static Schedule> {
+    SCHEDULE.get()
+}
+
+/// The installed schedule. `ThunderState::new` fails without one, so it is always there once the
+/// node is running.
+pub fn schedule() -> &'static Schedule {
+    get_schedule().expect("schedule should be set before the node is started")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activation(height: u32) -> Activation {
+        Activation {
+            height,
+            transaction_size_limit: None,
+            transaction_sigops_limit: None,
+            body_size_limit: None,
+            body_sigops_limit: None,
+            data_size_limit: None,
+            data_fee_rate: None,
+            coinbase_maturity: None,
+        }
+    }
+
+    #[test]
+    fn limits_change_at_activation_heights() {
+        let defaults = Limits::default();
+        let schedule = Schedule::from_activations(vec![
+            Activation {
+                data_size_limit: Some(160),
+                ..activation(200)
+            },
+            Activation {
+                body_size_limit: Some(16 * 1024 * 1024),
+                ..activation(100)
+            },
+        ])
+        .unwrap();
+        assert_eq!(schedule.limits(0), defaults);
+        assert_eq!(schedule.limits(99), defaults);
+        let at_100 = Limits {
+            body_size_limit: 16 * 1024 * 1024,
+            ..defaults
+        };
+        assert_eq!(schedule.limits(100), at_100);
+        assert_eq!(schedule.limits(199), at_100);
+        let at_200 = Limits {
+            data_size_limit: 160,
+            ..at_100
+        };
+        assert_eq!(schedule.limits(200), at_200);
+        assert_eq!(schedule.limits(u32::MAX), at_200);
+    }
+
+    #[test]
+    fn activation_at_genesis_replaces_defaults() {
+        let schedule = Schedule::from_activations(vec![Activation {
+            data_fee_rate: Some(1),
+            ..activation(0)
+        }])
+        .unwrap();
+        let limits = Limits {
+            data_fee_rate: 1,
+            ..Limits::default()
+        };
+        assert_eq!(schedule.limits, vec![(0, limits)]);
+        assert_eq!(schedule.limits(0), limits);
+    }
+
+    #[test]
+    fn coinbase_maturity_activates_at_first_nonzero_maturity() {
+        assert_eq!(Schedule::default().coinbase_maturity_activation(), None);
+        let schedule = Schedule::from_activations(vec![
+            Activation {
+                coinbase_maturity: Some(0),
+                ..activation(0)
+            },
+            Activation {
+                data_size_limit: Some(160),
+                ..activation(50)
+            },
+            Activation {
+                coinbase_maturity: Some(100),
+                ..activation(120)
+            },
+        ])
+        .unwrap();
+        assert_eq!(schedule.limits(119).coinbase_maturity, 0);
+        assert_eq!(schedule.limits(120).coinbase_maturity, 100);
+        assert_eq!(schedule.coinbase_maturity_activation(), Some(120));
+        let never = Schedule::from_activations(vec![Activation {
+            coinbase_maturity: Some(0),
+            ..activation(0)
+        }])
+        .unwrap();
+        assert_eq!(never.coinbase_maturity_activation(), None);
+    }
+
+    #[test]
+    fn duplicate_activation_heights_are_rejected() {
+        assert!(Schedule::from_activations(vec![activation(10), activation(10)]).is_err());
+    }
+
+    #[test]
+    fn schedule_can_only_be_set_once() {
+        // Other tests might have set it already.
+        let _ = set_schedule(Schedule::default());
+        assert!(get_schedule().is_some());
+        assert!(set_schedule(Schedule::default()).is_err());
+    }
+}
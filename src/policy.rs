@@ -0,0 +1,91 @@
+use crate::thunder::Thunder;
+use ddk::types::{Content, Transaction};
+use serde::{Deserialize, Serialize};
+
+/// Node-local rules for the transactions that this node sends and mines. They are not consensus
+/// rules, `ThunderState` never checks them, so transactions from peers that don't meet them still
+/// enter the mempool, they are just left out of the blocks that this node mines.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Minimum fee per byte of the serialized transaction, in sats.
+    pub min_fee_rate: u64,
+    /// `Content::Value` outputs worth less than this many sats are rejected.
+    pub dust_threshold: u64,
+    pub max_outputs: usize,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            min_fee_rate: 1,
+            dust_threshold: 546,
+            max_outputs: 256,
+        }
+    }
+}
+
+/// Upper bound for the serialized size of one authorization, a public key and a signature with
+/// their length prefixes.
+const AUTHORIZATION_SIZE: u64 = 8 + 32 + 8 + 64;
+
+/// Size that `transaction` will have once it is authorized, for computing fees from fee rates.
+pub fn estimate_size(transaction: &Transaction<Thunder>) -> Result<u64, bincode::Error> {
+    let size = bincode::serialized_size(transaction)?;
+    // One authorization per input, and the length of the authorizations vector.
+    Ok(size + transaction.inputs.len() as u64 * AUTHORIZATION_SIZE + 8)
+}
+
+impl Policy {
+    /// Check a transaction that pays `fee`. The fee rate is always computed from `estimate_size`,
+    /// the size that the wallet computes fees from, whether the transaction is authorized yet or
+    /// not, so a fee at exactly the minimum rate passes everywhere.
+    pub fn check(&self, transaction: &Transaction<Thunder>, fee: u64) -> Result<(), Error> {
+        let size = estimate_size(transaction)?;
+        let outputs = &transaction.outputs;
+        if outputs.len() > self.max_outputs {
+            return Err(Error::TooManyOutputs {
+                num_outputs: outputs.len(),
+                max_outputs: self.max_outputs,
+            });
+        }
+        for output in outputs {
+            if let Content::Value(value) = output.content {
+                if value < self.dust_threshold {
+                    return Err(Error::DustOutput {
+                        value,
+                        dust_threshold: self.dust_threshold,
+                    });
+                }
+            }
+        }
+        if (fee as u128) < self.min_fee_rate as u128 * size as u128 {
+            return Err(Error::FeeRateTooLow {
+                fee,
+                size,
+                min_fee_rate: self.min_fee_rate,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A transaction that the node doesn't relay, send or mine, although it might be valid.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("bincode error")]
+    Bincode(#[from] bincode::Error),
+    #[error("fee of {fee} sats for {size} bytes is below the minimum fee rate of {min_fee_rate} sats/byte")]
+    FeeRateTooLow {
+        fee: u64,
+        size: u64,
+        min_fee_rate: u64,
+    },
+    #[error("output of {value} sats is below the dust threshold of {dust_threshold} sats")]
+    DustOutput { value: u64, dust_threshold: u64 },
+    #[error("{num_outputs} outputs is more than the maximum of {max_outputs}")]
+    TooManyOutputs {
+        num_outputs: usize,
+        max_outputs: usize,
+    },
+}
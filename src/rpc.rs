@@ -0,0 +1,320 @@
+use std::net::SocketAddr;
+use std::path::Path;
+
+use crate::app::{self, App, MiningStats};
+use crate::block_template::{BlockTemplate, TemplateInfo};
+use crate::fee_estimator::FeeEstimate;
+use crate::thunder::Thunder;
+use ddk::authorization::Authorization;
+use ddk::bitcoin;
+use ddk::types::{
+    Address, AuthorizedTransaction, BlockHash, Body, Content, Header, OutPoint, Output,
+    Transaction, Txid,
+};
+use jsonrpsee::{
+    core::{async_trait, Error as RpcError, RpcResult},
+    proc_macros::rpc,
+    server::{Server, ServerHandle},
+};
+use serde::{Deserialize, Serialize};
+use tower_http::validate_request::ValidateRequestHeaderLayer;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub output: Output<Thunder>,
+}
+
+/// A transaction from a connected block, `index` is its position in the block body.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConfirmedTransaction {
+    pub height: u32,
+    pub index: u32,
+    pub transaction: Transaction<Thunder>,
+}
+
+#[rpc(server, client)]
+pub trait Rpc {
+    #[method(name = "get_height")]
+    async fn get_height(&self) -> RpcResult<u32>;
+
+    #[method(name = "get_best_hash")]
+    async fn get_best_hash(&self) -> RpcResult<BlockHash>;
+
+    #[method(name = "get_header")]
+    async fn get_header(&self, height: u32) -> RpcResult<Option<Header>>;
+
+    #[method(name = "get_body")]
+    async fn get_body(&self, height: u32) -> RpcResult<Option<Body<Authorization, Thunder>>>;
+
+    #[method(name = "get_mempool")]
+    async fn get_mempool(&self) -> RpcResult<Vec<AuthorizedTransaction<Authorization, Thunder>>>;
+
+    #[method(name = "get_transaction")]
+    async fn get_transaction(&self, txid: Txid) -> RpcResult<Option<ConfirmedTransaction>>;
+
+    /// Outpoints of all outputs paid to `address` in connected blocks, oldest first.
+    #[method(name = "get_address_history")]
+    async fn get_address_history(&self, address: Address) -> RpcResult<Vec<OutPoint>>;
+
+    /// Wallet UTXOs that are not spent by any mempool transaction.
+    #[method(name = "get_utxos")]
+    async fn get_utxos(&self) -> RpcResult<Vec<Utxo>>;
+
+    #[method(name = "get_new_address")]
+    async fn get_new_address(&self) -> RpcResult<Address>;
+
+    /// Build an unsigned transaction that spends the wallet UTXOs `inputs` on `outputs` and `fee`,
+    /// to be checked or edited before it goes to `sign_and_send`. `inputs` are picked from the
+    /// wallet UTXOs if there are none, and change goes back to a fresh wallet address.
+    #[method(name = "create_transaction")]
+    async fn create_transaction(
+        &self,
+        inputs: Vec<OutPoint>,
+        outputs: Vec<Output<Thunder>>,
+        fee: u64,
+    ) -> RpcResult<Transaction<Thunder>>;
+
+    /// Sign a transaction with the wallet keys and submit it to the mempool.
+    #[method(name = "sign_and_send")]
+    async fn sign_and_send(&self, transaction: Transaction<Thunder>) -> RpcResult<Txid>;
+
+    /// Pay `value` sats to `dest` by spending the wallet UTXOs `inputs`, leaving `fee` sats to the
+    /// miner. `inputs` are picked from the wallet UTXOs if there are none.
+    #[method(name = "transfer")]
+    async fn transfer(
+        &self,
+        dest: Address,
+        value: u64,
+        fee: u64,
+        inputs: Vec<OutPoint>,
+    ) -> RpcResult<Txid>;
+
+    /// Withdraw `value` sats to a mainchain address, paying `main_fee` sats on the mainchain and
+    /// `fee` sats on the sidechain, by spending the wallet UTXOs `inputs`.
+    /// `inputs` are picked from the wallet UTXOs if there are none.
+    #[method(name = "withdraw")]
+    async fn withdraw(
+        &self,
+        main_address: bitcoin::Address<bitcoin::address::NetworkUnchecked>,
+        value: u64,
+        main_fee: u64,
+        fee: u64,
+        inputs: Vec<OutPoint>,
+    ) -> RpcResult<Txid>;
+
+    /// Attempt to BMM a block, returns whether the block was confirmed and connected.
+    #[method(name = "mine")]
+    async fn mine(&self) -> RpcResult<bool>;
+
+    #[method(name = "get_mining_stats")]
+    async fn get_mining_stats(&self) -> RpcResult<MiningStats>;
+
+    /// Fee rates in sats per byte to get a transaction confirmed within 1, 3 and 6 blocks.
+    #[method(name = "estimate_fees")]
+    async fn estimate_fees(&self) -> RpcResult<Vec<FeeEstimate>>;
+
+    /// Summary of the transactions the next mined block would include.
+    #[method(name = "get_block_template")]
+    async fn get_block_template(&self) -> RpcResult<TemplateInfo>;
+
+    /// Create a mainchain deposit to a fresh wallet address, amounts are in sats.
+    #[method(name = "deposit")]
+    async fn deposit(&self, amount: u64, fee: u64) -> RpcResult<()>;
+}
+
+pub struct RpcServerImpl {
+    app: App,
+}
+
+fn custom_err<E: Into<app::Error>>(err: E) -> RpcError {
+    let err: app::Error = err.into();
+    RpcError::Custom(app::format_error_chain(&err))
+}
+
+#[async_trait]
+impl RpcServer for RpcServerImpl {
+    async fn get_height(&self) -> RpcResult<u32> {
+        self.app.node.get_height().map_err(custom_err)
+    }
+
+    async fn get_best_hash(&self) -> RpcResult<BlockHash> {
+        self.app.node.get_best_hash().map_err(custom_err)
+    }
+
+    async fn get_header(&self, height: u32) -> RpcResult<Option<Header>> {
+        self.app.node.get_header(height).map_err(custom_err)
+    }
+
+    async fn get_body(&self, height: u32) -> RpcResult<Option<Body<Authorization, Thunder>>> {
+        self.app.node.get_body(height).map_err(custom_err)
+    }
+
+    async fn get_mempool(&self) -> RpcResult<Vec<AuthorizedTransaction<Authorization, Thunder>>> {
+        self.app.node.get_all_transactions().map_err(custom_err)
+    }
+
+    async fn get_transaction(&self, txid: Txid) -> RpcResult<Option<ConfirmedTransaction>> {
+        let transaction = self.app.get_transaction(&txid).map_err(custom_err)?;
+        Ok(
+            transaction.map(|(location, transaction)| ConfirmedTransaction {
+                height: location.height,
+                index: location.index,
+                transaction,
+            }),
+        )
+    }
+
+    async fn get_address_history(&self, address: Address) -> RpcResult<Vec<OutPoint>> {
+        self.app.get_address_history(&address).map_err(custom_err)
+    }
+
+    async fn get_utxos(&self) -> RpcResult<Vec<Utxo>> {
+        let utxos = self.app.utxos.read().unwrap();
+        let mut utxos: Vec<_> = utxos
+            .iter()
+            .map(|(outpoint, output)| Utxo {
+                outpoint: *outpoint,
+                output: output.clone(),
+            })
+            .collect();
+        utxos.sort_by_key(|utxo| format!("{}", utxo.outpoint));
+        Ok(utxos)
+    }
+
+    async fn get_new_address(&self) -> RpcResult<Address> {
+        self.app.wallet.get_new_address().map_err(custom_err)
+    }
+
+    async fn create_transaction(
+        &self,
+        inputs: Vec<OutPoint>,
+        outputs: Vec<Output<Thunder>>,
+        fee: u64,
+    ) -> RpcResult<Transaction<Thunder>> {
+        self.app
+            .create_transaction(inputs, outputs, fee)
+            .map_err(custom_err)
+    }
+
+    async fn sign_and_send(&self, transaction: Transaction<Thunder>) -> RpcResult<Txid> {
+        self.app
+            .sign_and_send(transaction)
+            .await
+            .map_err(custom_err)
+    }
+
+    async fn transfer(
+        &self,
+        dest: Address,
+        value: u64,
+        fee: u64,
+        inputs: Vec<OutPoint>,
+    ) -> RpcResult<Txid> {
+        let output = Output {
+            address: dest,
+            content: Content::Value(value),
+        };
+        let transaction = self
+            .app
+            .create_transaction(inputs, vec![output], fee)
+            .map_err(custom_err)?;
+        self.app
+            .sign_and_send(transaction)
+            .await
+            .map_err(custom_err)
+    }
+
+    async fn withdraw(
+        &self,
+        main_address: bitcoin::Address<bitcoin::address::NetworkUnchecked>,
+        value: u64,
+        main_fee: u64,
+        fee: u64,
+        inputs: Vec<OutPoint>,
+    ) -> RpcResult<Txid> {
+        self.app
+            .check_main_address(&main_address)
+            .map_err(custom_err)?;
+        let output = Output {
+            address: self.app.get_change_address().map_err(custom_err)?,
+            content: Content::Withdrawal {
+                value,
+                main_address,
+                main_fee,
+            },
+        };
+        let transaction = self
+            .app
+            .create_transaction(inputs, vec![output], fee)
+            .map_err(custom_err)?;
+        self.app
+            .sign_and_send(transaction)
+            .await
+            .map_err(custom_err)
+    }
+
+    async fn mine(&self) -> RpcResult<bool> {
+        self.app.mine().await.map_err(custom_err)
+    }
+
+    async fn get_mining_stats(&self) -> RpcResult<MiningStats> {
+        Ok(self.app.mining_stats.lock().unwrap().clone())
+    }
+
+    async fn estimate_fees(&self) -> RpcResult<Vec<FeeEstimate>> {
+        self.app
+            .run_blocking(|app| app.estimate_fees())
+            .await
+            .map_err(custom_err)
+    }
+
+    async fn get_block_template(&self) -> RpcResult<TemplateInfo> {
+        let template =
+            BlockTemplate::build(&self.app.node, &self.app.policy).map_err(custom_err)?;
+        Ok(template.info)
+    }
+
+    async fn deposit(&self, amount: u64, fee: u64) -> RpcResult<()> {
+        let amount = bitcoin::Amount::from_sat(amount);
+        let fee = bitcoin::Amount::from_sat(fee);
+        self.app.deposit(amount, fee).await.map_err(custom_err)
+    }
+}
+
+/// Name of the file in the datadir that holds the RPC credentials when no user and password are
+/// configured, in the same `user:password` format as the mainchain node's cookie.
+pub const COOKIE_FILE_NAME: &str = ".cookie";
+const COOKIE_USER: &str = "__cookie__";
+
+/// Write a cookie file with a fresh random password and return the credentials in it. Only the
+/// user running the node can read it, so only that user's processes can use the RPC server.
+pub fn write_cookie(datadir: &Path) -> anyhow::Result<(String, String)> {
+    let password = hex::encode(rand::random::<[u8; 32]>());
+    let path = datadir.join(COOKIE_FILE_NAME);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&path)?;
+    std::io::Write::write_all(&mut file, format!("{COOKIE_USER}:{password}").as_bytes())?;
+    Ok((COOKIE_USER.into(), password))
+}
+
+/// Serve the RPC API to clients that authenticate with `user` and `password`, some methods spend
+/// wallet funds.
+pub async fn run_server(
+    app: App,
+    rpc_addr: SocketAddr,
+    user: &str,
+    password: &str,
+) -> anyhow::Result<ServerHandle> {
+    let middleware =
+        tower::ServiceBuilder::new().layer(ValidateRequestHeaderLayer::basic(user, password));
+    let server = Server::builder()
+        .set_middleware(middleware)
+        .build(rpc_addr)
+        .await?;
+    let handle = server.start(RpcServerImpl { app }.into_rpc());
+    Ok(handle)
+}
@@ -1,56 +1,338 @@
+use crate::params;
 use ddk::heed;
+use ddk::heed::types::{OwnedType, SerdeBincode, Str};
 use ddk::node::State;
-use ddk::types::GetValue;
+use ddk::types::{Body, Content, GetValue, MerkleRoot, OutPoint, Output, Transaction};
 use serde::{Deserialize, Serialize};
 
+/// Custom content of Thunder outputs.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
-pub struct Thunder;
+pub enum Thunder {
+    /// Arbitrary data and no value, used to anchor small commitments on the sidechain. Data
+    /// outputs can't be spent, see `ThunderState::is_unspendable`.
+    Data(Vec<u8>),
+    /// Value that can't be spent before the block at height `until`, for escrow and vesting.
+    Locked { value: u64, until: u32 },
+}
 
 impl GetValue for Thunder {
     fn get_value(&self) -> u64 {
-        0
+        match self {
+            Self::Data(_) => 0,
+            Self::Locked { value, .. } => *value,
+        }
     }
 }
 
 #[derive(Clone)]
-pub struct ThunderState;
+pub struct ThunderState {
+    /// Height of the block that created the coinbase outputs with a merkle root, for coinbase
+    /// maturity.
+    coinbases: heed::Database<SerdeBincode<MerkleRoot>, OwnedType<u32>>,
+    /// Merkle root of the coinbase that the body at a height added to `coinbases`, if it had one.
+    /// Only kept for the last `UNDO_DEPTH` bodies.
+    coinbase_undo: heed::Database<OwnedType<u32>, SerdeBincode<Option<MerkleRoot>>>,
+    /// The height of the last connected body under `TIP`, and of the first body this state ever
+    /// connected under `FIRST`.
+    heights: heed::Database<Str, OwnedType<u32>>,
+}
 
 impl ThunderState {
-    pub fn transaction_size_limit(_height: u32) -> u64 {
-        1024 * 1024
+    /// How many of the last connected bodies can be disconnected.
+    pub const UNDO_DEPTH: u32 = 100;
+    const TIP: &'static str = "tip";
+    const FIRST: &'static str = "first";
+
+    /// Record the coinbase of the body at `height`, this is all that `connect_body` does.
+    pub fn connect_coinbase(
+        &self,
+        txn: &mut heed::RwTxn,
+        height: u32,
+        body: &Body<ddk::authorization::Authorization, Thunder>,
+    ) -> Result<(), Error> {
+        let merkle_root = if body.coinbase.is_empty() {
+            None
+        } else {
+            let merkle_root = body.compute_merkle_root();
+            self.coinbases.put(txn, &merkle_root, &height)?;
+            Some(merkle_root)
+        };
+        self.coinbase_undo.put(txn, &height, &merkle_root)?;
+        if let Some(prune_height) = height.checked_sub(Self::UNDO_DEPTH) {
+            self.coinbase_undo.delete(txn, &prune_height)?;
+        }
+        if self.heights.get(txn, Self::FIRST)?.is_none() {
+            self.heights.put(txn, Self::FIRST, &height)?;
+        }
+        self.heights.put(txn, Self::TIP, &height)?;
+        Ok(())
+    }
+
+    /// Forget everything this state recorded, so that it can be rebuilt by connecting the chain
+    /// again from genesis, see `App::rebuild_state`.
+    pub fn clear(&self, txn: &mut heed::RwTxn) -> Result<(), Error> {
+        self.coinbases.clear(txn)?;
+        self.coinbase_undo.clear(txn)?;
+        self.heights.clear(txn)?;
+        Ok(())
+    }
+
+    /// Consensus rule for a transaction in a block at `height`: every coinbase output that it
+    /// spends has matured.
+    fn check_coinbase_maturity(
+        &self,
+        txn: &heed::RoTxn,
+        height: u32,
+        inputs: &[OutPoint],
+    ) -> Result<(), Error> {
+        for input in inputs {
+            if let OutPoint::Coinbase { merkle_root, .. } = input {
+                match self.get_coinbase_height(txn, merkle_root)? {
+                    Some(coinbase_height) => {
+                        let mature_at = Self::coinbase_mature_at(coinbase_height);
+                        if height < mature_at {
+                            return Err(Error::ImmatureCoinbase { mature_at });
+                        }
+                    }
+                    None => self.check_unrecorded_coinbase(
+                        txn,
+                        params::schedule().coinbase_maturity_activation(),
+                    )?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A coinbase without a recorded height was created before the first body that this state
+    /// connected. If every coinbase that has to mature from `activation` on was created after
+    /// that, it is exempt, otherwise its height is needed and the state has to be rebuilt from
+    /// the local chain with `--reindex`.
+    fn check_unrecorded_coinbase(
+        &self,
+        txn: &heed::RoTxn,
+        activation: Option<u32>,
+    ) -> Result<(), Error> {
+        let Some(activation) = activation else {
+            return Ok(());
+        };
+        let first = self.heights.get(txn, Self::FIRST)?.unwrap_or(0);
+        if first <= activation {
+            Ok(())
+        } else {
+            Err(Error::UnrecordedCoinbase { first, activation })
+        }
+    }
+
+    /// Roll back what `connect_body` stored for the body at `height`, which has to be the tip
+    /// and one of the last `UNDO_DEPTH` bodies that were connected. Disconnecting the first body
+    /// leaves the state as if it never connected anything.
+    ///
+    /// ddk doesn't disconnect blocks, so nothing calls this yet, this is what it has to call once
+    /// it does. The index follows reorgs on its own, see `Index::sync`.
+    pub fn disconnect_body(&self, txn: &mut heed::RwTxn, height: u32) -> Result<(), Error> {
+        let tip = self.heights.get(txn, Self::TIP)?;
+        if tip != Some(height) {
+            return Err(Error::NotTip { height, tip });
+        }
+        let merkle_root = self
+            .coinbase_undo
+            .get(txn, &height)?
+            .ok_or(Error::NoUndoData { height })?;
+        if let Some(merkle_root) = merkle_root {
+            self.coinbases.delete(txn, &merkle_root)?;
+        }
+        self.coinbase_undo.delete(txn, &height)?;
+        let first = self.heights.get(txn, Self::FIRST)?;
+        match height.checked_sub(1) {
+            Some(prev_height) if first != Some(height) => {
+                self.heights.put(txn, Self::TIP, &prev_height)?
+            }
+            _ => {
+                self.heights.delete(txn, Self::TIP)?;
+                self.heights.delete(txn, Self::FIRST)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Height of the block whose coinbase has `merkle_root`, `None` for blocks connected before
+    /// coinbases were recorded.
+    pub fn get_coinbase_height(
+        &self,
+        txn: &heed::RoTxn,
+        merkle_root: &MerkleRoot,
+    ) -> Result<Option<u32>, Error> {
+        Ok(self.coinbases.get(txn, merkle_root)?)
+    }
+
+    pub fn transaction_size_limit(height: u32) -> u64 {
+        params::schedule().limits(height).transaction_size_limit
+    }
+
+    pub fn transaction_sigops_limit(height: u32) -> u64 {
+        params::schedule().limits(height).transaction_sigops_limit
+    }
+
+    pub fn body_size_limit(height: u32) -> u64 {
+        params::schedule().limits(height).body_size_limit
+    }
+
+    pub fn body_sigops_limit(height: u32) -> u64 {
+        params::schedule().limits(height).body_sigops_limit
+    }
+
+    pub fn data_size_limit(height: u32) -> u64 {
+        params::schedule().limits(height).data_size_limit
+    }
+
+    pub fn data_fee_rate(height: u32) -> u64 {
+        params::schedule().limits(height).data_fee_rate
+    }
+
+    pub fn coinbase_maturity(height: u32) -> u32 {
+        params::schedule().limits(height).coinbase_maturity
+    }
+
+    /// First block height that can spend the outputs of a coinbase confirmed at `coinbase_height`.
+    pub fn coinbase_mature_at(coinbase_height: u32) -> u32 {
+        coinbase_height.saturating_add(Self::coinbase_maturity(coinbase_height))
+    }
+
+    /// First block height that can spend `output`, `None` if it isn't locked.
+    pub fn locked_until(output: &Output<Thunder>) -> Option<u32> {
+        match output.content {
+            Content::Custom(Thunder::Locked { until, .. }) => Some(until),
+            _ => None,
+        }
+    }
+
+    /// Consensus rule for a transaction in a block at `height` that spends `spent_utxos`: none of
+    /// them may still be locked.
+    pub fn check_locks(height: u32, spent_utxos: &[Output<Thunder>]) -> Result<(), Error> {
+        for spent_utxo in spent_utxos {
+            if let Some(until) = Self::locked_until(spent_utxo) {
+                if height < until {
+                    return Err(Error::OutputLocked { until });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `output` can never be spent. Data outputs carry no value, so spending them is
+    /// rejected rather than having them look like coins. ddk still keeps them in its UTXO set, the
+    /// wallet leaves them out.
+    pub fn is_unspendable(output: &Output<Thunder>) -> bool {
+        matches!(output.content, Content::Custom(Thunder::Data(_)))
+    }
+
+    /// Consensus rules for data outputs: no input spends one, each one is within the size limit,
+    /// and the fee pays for all of them.
+    pub fn check_data(
+        height: u32,
+        transaction: &ddk::types::FilledTransaction<Thunder>,
+    ) -> Result<(), Error> {
+        if transaction.spent_utxos.iter().any(Self::is_unspendable) {
+            return Err(Error::DataSpent);
+        }
+        let mut data_size = 0;
+        for output in &transaction.transaction.outputs {
+            if let Content::Custom(Thunder::Data(data)) = &output.content {
+                let size = data.len() as u64;
+                let limit = Self::data_size_limit(height);
+                if size > limit {
+                    return Err(Error::DataTooBig { size, limit });
+                }
+                data_size += size;
+            }
+        }
+        let value_in: u64 = transaction
+            .spent_utxos
+            .iter()
+            .map(GetValue::get_value)
+            .sum();
+        let value_out: u64 = transaction
+            .transaction
+            .outputs
+            .iter()
+            .map(GetValue::get_value)
+            .sum();
+        let fee = value_in.saturating_sub(value_out);
+        let required = data_size.saturating_mul(Self::data_fee_rate(height));
+        if fee < required {
+            return Err(Error::DataFeeTooLow { fee, required });
+        }
+        Ok(())
     }
 
-    pub fn transaction_sigops_limit(_height: u32) -> u64 {
-        8 * 1024
+    /// Verifying one signature.
+    const AUTHORIZATION_SIGOPS: u64 = 1;
+    /// Withdrawals cost more because every one of them has to be put into a mainchain bundle.
+    const WITHDRAWAL_SIGOPS: u64 = 4;
+
+    pub fn content_sigops(content: &Content<Thunder>) -> u64 {
+        match content {
+            Content::Value(_) => 0,
+            Content::Withdrawal { .. } => Self::WITHDRAWAL_SIGOPS,
+            Content::Custom(_) => 0,
+        }
     }
 
-    pub fn body_size_limit(_height: u32) -> u64 {
-        8 * 1024 * 1024
+    /// Every input of a transaction needs exactly one authorization.
+    pub fn transaction_sigops(transaction: &Transaction<Thunder>) -> u64 {
+        let authorization_sigops = transaction.inputs.len() as u64 * Self::AUTHORIZATION_SIGOPS;
+        let content_sigops: u64 = transaction
+            .outputs
+            .iter()
+            .map(|output| Self::content_sigops(&output.content))
+            .sum();
+        authorization_sigops + content_sigops
     }
 
-    pub fn body_sigops_limit(_height: u32) -> u64 {
-        8 * 8 * 1024
+    pub fn body_sigops(body: &Body<ddk::authorization::Authorization, Thunder>) -> u64 {
+        let authorization_sigops = body.authorizations.len() as u64 * Self::AUTHORIZATION_SIGOPS;
+        let content_sigops: u64 = body
+            .coinbase
+            .iter()
+            .chain(
+                body.transactions
+                    .iter()
+                    .flat_map(|transaction| &transaction.outputs),
+            )
+            .map(|output| Self::content_sigops(&output.content))
+            .sum();
+        authorization_sigops + content_sigops
     }
 }
 
 impl State<ddk::authorization::Authorization, Thunder> for ThunderState {
     const THIS_SIDECHAIN: u8 = 9;
-    const NUM_DBS: u32 = 5;
+    const NUM_DBS: u32 = 3;
 
     type Error = Error;
 
-    fn new(_env: &heed::Env) -> Result<Self, Self::Error> {
-        Ok(Self)
+    fn new(env: &heed::Env) -> Result<Self, Self::Error> {
+        params::get_schedule().ok_or(Error::NoSchedule)?;
+        let coinbases = env.create_database(Some("coinbases"))?;
+        let coinbase_undo = env.create_database(Some("coinbase_undo"))?;
+        let heights = env.create_database(Some("heights"))?;
+        Ok(Self {
+            coinbases,
+            coinbase_undo,
+            heights,
+        })
     }
 
     fn validate_filled_transaction(
         &self,
-        _txn: &heed::RoTxn,
+        txn: &heed::RoTxn,
         height: u32,
         _state: &ddk::state::State<ddk::authorization::Authorization, Thunder>,
         transaction: &ddk::types::FilledTransaction<Thunder>,
     ) -> Result<(), Self::Error> {
-        if transaction.transaction.inputs.len() as u64
+        self.check_coinbase_maturity(txn, height, &transaction.transaction.inputs)?;
+        if ThunderState::transaction_sigops(&transaction.transaction)
             > ThunderState::transaction_sigops_limit(height)
         {
             return Err(Error::TooManySigOpsInTransaction);
@@ -59,34 +341,44 @@ impl State<ddk::authorization::Authorization, Thunder> for ThunderState {
         if serialized_transaction.len() as u64 > ThunderState::transaction_size_limit(height) {
             return Err(Error::TransactionTooBig);
         }
+        ThunderState::check_locks(height, &transaction.spent_utxos)?;
+        ThunderState::check_data(height, transaction)?;
         Ok(())
     }
 
     fn validate_body(
         &self,
-        _txn: &heed::RoTxn,
+        txn: &heed::RoTxn,
         height: u32,
-        _state: &ddk::state::State<ddk::authorization::Authorization, Thunder>,
+        state: &ddk::state::State<ddk::authorization::Authorization, Thunder>,
         body: &ddk::types::Body<ddk::authorization::Authorization, Thunder>,
     ) -> Result<(), Self::Error> {
-        if body.authorizations.len() as u64 > ThunderState::body_sigops_limit(height) {
+        if ThunderState::body_sigops(body) > ThunderState::body_sigops_limit(height) {
             return Err(Error::TooManySigOpsInBody);
         }
         let serialized_body = bincode::serialize(body)?;
         if serialized_body.len() as u64 > ThunderState::body_size_limit(height) {
             return Err(Error::BodyTooBig);
         }
+        // Transactions in a body don't go through `validate_filled_transaction`, the rules that
+        // need the spent outputs are checked here again.
+        for transaction in &body.transactions {
+            self.check_coinbase_maturity(txn, height, &transaction.inputs)?;
+            let transaction = state.fill_transaction(txn, transaction)?;
+            ThunderState::check_locks(height, &transaction.spent_utxos)?;
+            ThunderState::check_data(height, &transaction)?;
+        }
         Ok(())
     }
 
     fn connect_body(
         &self,
-        _txn: &mut heed::RwTxn,
-        _height: u32,
+        txn: &mut heed::RwTxn,
+        height: u32,
         _state: &ddk::state::State<ddk::authorization::Authorization, Thunder>,
-        _body: &ddk::types::Body<ddk::authorization::Authorization, Thunder>,
+        body: &ddk::types::Body<ddk::authorization::Authorization, Thunder>,
     ) -> Result<(), Self::Error> {
-        Ok(())
+        self.connect_coinbase(txn, height, body)
     }
 }
 
@@ -94,6 +386,14 @@ impl State<ddk::authorization::Authorization, Thunder> for ThunderState {
 pub enum Error {
     #[error("bincode error")]
     Bincode(#[from] bincode::Error),
+    #[error("heed error")]
+    Heed(#[from] heed::Error),
+    #[error("consensus schedule is not set")]
+    NoSchedule,
+    #[error("can't disconnect the body at height {height}, the tip is at {tip:?}")]
+    NotTip { height: u32, tip: Option<u32> },
+    #[error("no undo data for the body at height {height}")]
+    NoUndoData { height: u32 },
     #[error("too many sigops in body")]
     TooManySigOpsInBody,
     #[error("too many sigops in transaction")]
@@ -102,6 +402,23 @@ pub enum Error {
     BodyTooBig,
     #[error("transaction too big")]
     TransactionTooBig,
+    #[error("coinbase output can't be spent before height {mature_at}")]
+    ImmatureCoinbase { mature_at: u32 },
+    #[error(
+        "coinbase heights are only known from height {first} on, but coinbases mature from \
+         height {activation} on, restart with --reindex to record them from the local chain"
+    )]
+    UnrecordedCoinbase { first: u32, activation: u32 },
+    #[error("output is locked until height {until}")]
+    OutputLocked { until: u32 },
+    #[error("data output of {size} bytes is over the limit of {limit} bytes")]
+    DataTooBig { size: u64, limit: u64 },
+    #[error("fee of {fee} sats is below the {required} sats required for the data outputs")]
+    DataFeeTooLow { fee: u64, required: u64 },
+    #[error("data outputs can't be spent")]
+    DataSpent,
+    #[error("state error")]
+    State(#[from] ddk::state::Error),
 }
 
 impl ddk::node::CustomError for Error {}
@@ -109,3 +426,228 @@ impl ddk::node::CustomError for Error {}
 pub type Node = ddk::node::Node<ddk::authorization::Authorization, Thunder, ThunderState>;
 pub type Wallet = ddk::wallet::Wallet<Thunder>;
 pub type Miner = ddk::miner::Miner<ddk::authorization::Authorization, Thunder>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ddk::types::Address;
+
+    fn state() -> (tempfile::TempDir, heed::Env, ThunderState) {
+        // Tests share the process, whichever sets it first wins.
+        let _ = params::set_schedule(params::Schedule::default());
+        let dir = tempfile::tempdir().unwrap();
+        let env = heed::EnvOpenOptions::new()
+            .max_dbs(ThunderState::NUM_DBS)
+            .open(dir.path())
+            .unwrap();
+        let state = ThunderState::new(&env).unwrap();
+        (dir, env, state)
+    }
+
+    fn body(height: u32) -> Body<ddk::authorization::Authorization, Thunder> {
+        let coinbase = vec![Output {
+            address: Address::from([height as u8; 20]),
+            content: Content::Value(1000 + height as u64),
+        }];
+        Body {
+            coinbase,
+            transactions: vec![],
+            authorizations: vec![],
+        }
+    }
+
+    /// Everything in the three databases of the state.
+    #[derive(Debug, Default, PartialEq)]
+    struct Snapshot {
+        coinbases: Vec<(MerkleRoot, u32)>,
+        coinbase_undo: Vec<(u32, Option<MerkleRoot>)>,
+        heights: Vec<(String, u32)>,
+    }
+
+    fn snapshot(env: &heed::Env, state: &ThunderState) -> Snapshot {
+        let txn = env.read_txn().unwrap();
+        Snapshot {
+            coinbases: state
+                .coinbases
+                .iter(&txn)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect(),
+            coinbase_undo: state
+                .coinbase_undo
+                .iter(&txn)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect(),
+            heights: state
+                .heights
+                .iter(&txn)
+                .unwrap()
+                .map(|entry| {
+                    let (key, height) = entry.unwrap();
+                    (key.to_string(), height)
+                })
+                .collect(),
+        }
+    }
+
+    /// Connect bodies from `first` on one at a time, then disconnect them again, and check that
+    /// every disconnect restores exactly what was there before the matching connect.
+    fn connect_and_disconnect_all(first: u32) {
+        let (_dir, env, state) = state();
+        const N: u32 = 5;
+        let mut snapshots = vec![];
+        for height in first..first + N {
+            snapshots.push(snapshot(&env, &state));
+            let mut body = body(height);
+            // One body without a coinbase.
+            if height == first + 2 {
+                body.coinbase.clear();
+            }
+            let mut txn = env.write_txn().unwrap();
+            state.connect_coinbase(&mut txn, height, &body).unwrap();
+            txn.commit().unwrap();
+        }
+        let tip_root = body(first + N - 1).compute_merkle_root();
+        let txn = env.read_txn().unwrap();
+        assert_eq!(
+            state.get_coinbase_height(&txn, &tip_root).unwrap(),
+            Some(first + N - 1)
+        );
+        drop(txn);
+        for height in (first..first + N).rev() {
+            let mut txn = env.write_txn().unwrap();
+            state.disconnect_body(&mut txn, height).unwrap();
+            txn.commit().unwrap();
+            assert_eq!(
+                snapshot(&env, &state),
+                snapshots[(height - first) as usize],
+                "after disconnecting height {height}"
+            );
+        }
+        assert_eq!(snapshot(&env, &state), Snapshot::default());
+    }
+
+    #[test]
+    fn disconnecting_every_body_restores_every_previous_state() {
+        connect_and_disconnect_all(0);
+    }
+
+    #[test]
+    fn disconnecting_every_body_after_a_late_start_restores_every_previous_state() {
+        connect_and_disconnect_all(7);
+    }
+
+    #[test]
+    fn locked_outputs_are_spendable_from_their_height_on() {
+        let output = |content| Output {
+            address: Address::from([1; 20]),
+            content,
+        };
+        let spent_utxos = vec![
+            output(Content::Value(1000)),
+            output(Content::Custom(Thunder::Locked {
+                value: 1000,
+                until: 10,
+            })),
+            output(Content::Custom(Thunder::Locked {
+                value: 1000,
+                until: 20,
+            })),
+        ];
+        assert!(matches!(
+            ThunderState::check_locks(9, &spent_utxos),
+            Err(Error::OutputLocked { until: 10 })
+        ));
+        assert!(matches!(
+            ThunderState::check_locks(10, &spent_utxos),
+            Err(Error::OutputLocked { until: 20 })
+        ));
+        assert!(ThunderState::check_locks(20, &spent_utxos).is_ok());
+        assert!(ThunderState::check_locks(0, &spent_utxos[..1]).is_ok());
+        assert!(ThunderState::check_locks(0, &[]).is_ok());
+    }
+
+    #[test]
+    fn disconnect_below_tip_is_rejected() {
+        let (_dir, env, state) = state();
+        let mut txn = env.write_txn().unwrap();
+        for height in 0..3 {
+            state
+                .connect_coinbase(&mut txn, height, &body(height))
+                .unwrap();
+        }
+        let before = state.coinbases.len(&txn).unwrap();
+        assert!(matches!(
+            state.disconnect_body(&mut txn, 1),
+            Err(Error::NotTip {
+                height: 1,
+                tip: Some(2)
+            })
+        ));
+        assert_eq!(state.coinbases.len(&txn).unwrap(), before);
+    }
+
+    #[test]
+    fn unrecorded_coinbase_needs_reindex_after_activation() {
+        let (_dir, env, state) = state();
+        let mut txn = env.write_txn().unwrap();
+        state.connect_coinbase(&mut txn, 5, &body(5)).unwrap();
+        assert!(state.check_unrecorded_coinbase(&txn, None).is_ok());
+        assert!(state.check_unrecorded_coinbase(&txn, Some(5)).is_ok());
+        assert!(matches!(
+            state.check_unrecorded_coinbase(&txn, Some(0)),
+            Err(Error::UnrecordedCoinbase {
+                first: 5,
+                activation: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn replaying_the_chain_records_the_same_state() {
+        let (_dir, env, state) = state();
+        let mut txn = env.write_txn().unwrap();
+        for height in 0..5 {
+            state
+                .connect_coinbase(&mut txn, height, &body(height))
+                .unwrap();
+        }
+        txn.commit().unwrap();
+        let connected = snapshot(&env, &state);
+        let mut txn = env.write_txn().unwrap();
+        state.clear(&mut txn).unwrap();
+        for height in 0..5 {
+            state
+                .connect_coinbase(&mut txn, height, &body(height))
+                .unwrap();
+        }
+        txn.commit().unwrap();
+        assert_eq!(snapshot(&env, &state), connected);
+    }
+
+    #[test]
+    fn undo_data_is_pruned_past_depth() {
+        let (_dir, env, state) = state();
+        let num_bodies = ThunderState::UNDO_DEPTH + 10;
+        let mut txn = env.write_txn().unwrap();
+        for height in 0..num_bodies {
+            state
+                .connect_coinbase(&mut txn, height, &body(height))
+                .unwrap();
+        }
+        assert_eq!(
+            state.coinbase_undo.len(&txn).unwrap(),
+            ThunderState::UNDO_DEPTH as u64
+        );
+        // Coinbase heights are kept for maturity, only the undo data goes.
+        assert_eq!(state.coinbases.len(&txn).unwrap(), num_bodies as u64);
+        for height in (10..num_bodies).rev() {
+            state.disconnect_body(&mut txn, height).unwrap();
+        }
+        assert!(matches!(
+            state.disconnect_body(&mut txn, 9),
+            Err(Error::NoUndoData { height: 9 })
+        ));
+    }
+}